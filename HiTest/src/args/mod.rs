@@ -11,6 +11,31 @@ pub struct RunArgs {
     pub debug_test: Option<String>,
     pub serial: bool,
     pub max_threads: Option<usize>,
+    pub tags: Vec<String>,
+    pub exclude_tags: Vec<String>,
+    pub filters: Vec<String>,
+    pub skips: Vec<String>,
+    // values overriding the args of the test config, -D wins over --vars
+    pub defines: Vec<String>,
+    pub vars_files: Vec<String>,
+    pub list: bool,
+    pub retries: Option<usize>,
+    pub max_failures: Option<usize>,
+    pub seed: Option<u64>,
+    pub shuffle: bool,
+    pub death_timeout_ms: Option<u64>,
+    pub death_mode: Option<String>,
+    pub isolate: bool,
+    pub executor: Option<String>,
+    pub soak: Option<String>,
+    pub soak_interval: Option<String>,
+    pub bench_csv: Option<String>,
+    pub fuzz: bool,
+    // the test case to minimize and where to write the result
+    pub minimize: Option<String>,
+    pub minimize_output: Option<String>,
+    // internal, run one death test read from stdin
+    pub death_case: bool,
 }
 
 impl RunArgs {
@@ -32,10 +57,15 @@ fn parse_command_line(matches: &ArgMatches) -> RunArgs {
             .value_of("inputs")
             .expect("failed to get library config path")
             .to_string();
-        test_path = matches
-            .value_of("cases")
-            .expect("failed to get test cases config path")
-            .to_string();
+        // the death test is read from stdin instead
+        test_path = if matches.is_present("death-case") {
+            String::new()
+        } else {
+            matches
+                .value_of("cases")
+                .expect("failed to get test cases config path")
+                .to_string()
+        };
     }
     let debug_test = if matches.is_present("debug") {
         Some(matches.value_of("debug").unwrap().to_string())
@@ -48,6 +78,35 @@ fn parse_command_line(matches: &ArgMatches) -> RunArgs {
         None
     };
 
+    let values_of = |name: &str| -> Vec<String> {
+        matches
+            .values_of(name)
+            .map(|values| values.map(|v| v.to_string()).collect())
+            .unwrap_or_default()
+    };
+
+    let retries: Option<usize> = matches
+        .value_of("retries")
+        .map(|v| v.parse().expect("--retries should be a number"));
+
+    let max_failures: Option<usize> = if matches.is_present("fail-fast") {
+        Some(1)
+    } else {
+        matches
+            .value_of("max-failures")
+            .map(|v| v.parse().expect("--max-failures should be a number"))
+    };
+
+    let seed: Option<u64> = matches
+        .value_of("seed")
+        .map(|v| v.parse().expect("--seed should be a number"));
+
+    let death_timeout_ms: Option<u64> = matches
+        .value_of("death-timeout")
+        .map(|v| v.parse().expect("--death-timeout should be a number of milliseconds"));
+
+    let minimize = matches.subcommand_matches("minimize");
+
     let mut log_lvl: &str = matches.value_of("log").unwrap_or("info");
     if (log_lvl == "1") || (log_lvl == "error") {
         log_lvl = "error";
@@ -66,6 +125,28 @@ fn parse_command_line(matches: &ArgMatches) -> RunArgs {
         debug_test,
         serial: matches.is_present("serial"),
         max_threads,
+        tags: values_of("tag"),
+        exclude_tags: values_of("exclude-tag"),
+        filters: values_of("filter"),
+        skips: values_of("skip"),
+        defines: values_of("define"),
+        vars_files: values_of("vars"),
+        list: matches.is_present("list"),
+        retries,
+        max_failures,
+        seed,
+        shuffle: matches.is_present("shuffle"),
+        death_timeout_ms,
+        death_mode: matches.value_of("death-mode").map(|v| v.to_string()),
+        isolate: matches.is_present("isolate"),
+        executor: matches.value_of("executor").map(|v| v.to_string()),
+        soak: matches.value_of("soak").map(|v| v.to_string()),
+        soak_interval: matches.value_of("soak-interval").map(|v| v.to_string()),
+        bench_csv: matches.value_of("bench-csv").map(|v| v.to_string()),
+        fuzz: matches.is_present("fuzz"),
+        minimize: minimize.and_then(|m| m.value_of("test")).map(|v| v.to_string()),
+        minimize_output: minimize.and_then(|m| m.value_of("output")).map(|v| v.to_string()),
+        death_case: matches.is_present("death-case"),
     }
 }
 
@@ -83,6 +164,7 @@ fn init_command_line() -> ArgMatches {
                 r#"a toml file path wthich contains test cases, see sample/tc_libmalloc.toml"#,
             )
             .takes_value(true)
+            .global(true)
             .required(false),
     )
     .arg(
@@ -92,6 +174,7 @@ fn init_command_line() -> ArgMatches {
             .value_name("log level")
             .help("to control the log level, valid value contains [1,2,3,4]. which means [debug, info, warn, error]. default is info")
             .takes_value(true)
+            .global(true)
             .required(false),
     )
     .arg(
@@ -101,6 +184,7 @@ fn init_command_line() -> ArgMatches {
             .value_name("input libs config file")
             .help("a toml file contains all dependency libs")
             .takes_value(true)
+            .global(true)
             .required(false),
     )
     .arg(
@@ -132,6 +216,197 @@ fn init_command_line() -> ArgMatches {
             .takes_value(true)
             .required(false),
     )
+    .arg(
+        Arg::with_name("tag")
+            .long("tag")
+            .value_name("tags")
+            .help(r"only run test cases with this tag, can be given multiple times.
+            join tags with '+' to require all of them, e.g. --tag smoke+dma.
+            a test case is run when it matches any of the given --tag values.")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("exclude-tag")
+            .long("exclude-tag")
+            .value_name("tags")
+            .help(r"do not run test cases with this tag, can be given multiple times.
+            join tags with '+' to exclude only test cases with all of them.")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("filter")
+            .long("filter")
+            .value_name("pattern")
+            .help(r"only run test cases whose name matches this pattern, can be given multiple times.
+            the pattern is a glob such as 'test_rw_*', or a regex when prefixed with 're:'.
+            both the test name and the names of sub tests expanded from inputs are matched.")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("skip")
+            .long("skip")
+            .value_name("pattern")
+            .help("do not run test cases whose name matches this pattern, same syntax as --filter, can be given multiple times.")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("define")
+            .short('D')
+            .long("define")
+            .value_name("key=value")
+            .help(r"set the value of an arg, can be given multiple times.
+            it overrides the arg in the input groups and shared_inputs, and gives $key
+            to the cmds referring to it. wins over --vars, a later one wins.")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .global(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("vars")
+            .long("vars")
+            .value_name("vars file")
+            .help(r"a toml file of arg values applied like -D, a value may also be a list or a range.
+            can be given multiple times, a later file wins.")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .global(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("list")
+            .long("list")
+            .help("print the test cases that would run and exit without running them")
+            .takes_value(false)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("retries")
+            .long("retries")
+            .value_name("retry times")
+            .help("re-execute a failed test case up to this many times, the retries of a test case overrides it")
+            .takes_value(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("fail-fast")
+            .long("fail-fast")
+            .help("stop running new test cases after the first failure, same as --max-failures 1")
+            .takes_value(false)
+            .conflicts_with("max-failures")
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("max-failures")
+            .long("max-failures")
+            .value_name("failures")
+            .help(r"stop running new test cases after this many failures,
+            the running test cases are finished and the envs exit are still applied.")
+            .takes_value(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("seed")
+            .long("seed")
+            .value_name("seed")
+            .help(r"seed of all randomized decisions, such as grouping tests by max-thread and --shuffle.
+            a random seed is used and printed when not given.")
+            .takes_value(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("shuffle")
+            .long("shuffle")
+            .help("run concurrency groups, test cases and serial sub tests in random order")
+            .takes_value(false)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("death-timeout")
+            .long("death-timeout")
+            .value_name("milliseconds")
+            .help("kill the child process of a death test after this many milliseconds, default is 1000. the death_timeout_ms of a test case overrides it")
+            .takes_value(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("death-mode")
+            .long("death-mode")
+            .value_name("fork|exec")
+            .help(r"how to start the child process of a death test, default is fork.
+            exec starts a fresh hitest process which loads the libraries again and runs only this test.
+            the death_mode of a test case overrides it")
+            .possible_values(["fork", "exec"])
+            .takes_value(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("isolate")
+            .long("isolate")
+            .help(r"run every test case in a child process, a crash fails that test case only and the run goes on.
+            the child is started as --death-mode says, the isolate of a test case overrides it")
+            .takes_value(false)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("executor")
+            .long("executor")
+            .value_name("rayon|threads")
+            .help(r"where the threads of thread_num and concurrency groups run, default is rayon.
+            rayon runs them on a pool sized to the cpu count, threads creates one OS thread for each of them")
+            .possible_values(["rayon", "threads"])
+            .takes_value(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("soak")
+            .long("soak")
+            .value_name("duration")
+            .help(r"run all test cases again and again until this much time passed, such as 8h, 30m or 1h30m.
+            iterations, failure rate, rss and fd count growth are reported periodically")
+            .takes_value(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("soak-interval")
+            .long("soak-interval")
+            .value_name("duration")
+            .help("how often a soak run or a test case with duration prints its report, default is 1m")
+            .takes_value(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("bench-csv")
+            .long("bench-csv")
+            .value_name("file")
+            .help("write the results of all benchmarks of this run to this csv file")
+            .takes_value(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("fuzz")
+            .long("fuzz")
+            .help(r"run the fuzz campaigns of the test config instead of the test cases.
+            every random cmd sequence runs in a child process, the failing ones are saved as test cases")
+            .takes_value(false)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name("death-case")
+            .long("death-case")
+            .help("internal, run one death test read from stdin")
+            .hide(true)
+            .takes_value(false)
+            .required(false),
+    )
     .arg(
         Arg::with_name("sample")
         .short('s')
@@ -141,5 +416,26 @@ fn init_command_line() -> ArgMatches {
             .takes_value(false)
             .required(false),
     )
+    .subcommand(
+        App::new("minimize")
+            .about(r"shrink a failing test case to the fewest cmds and simplest arg values failing the same way.
+            every candidate runs in a child process, the minimal test case is printed as toml")
+            .arg(
+                Arg::with_name("test")
+                    .value_name("test case")
+                    .help("name of the test case, or of a sub test expanded from its inputs")
+                    .takes_value(true)
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("output")
+                    .short('o')
+                    .long("output")
+                    .value_name("file")
+                    .help("write the minimal test case to this file instead of stdout")
+                    .takes_value(true)
+                    .required(false),
+            ),
+    )
     .get_matches()
 }
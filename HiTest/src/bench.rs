@@ -0,0 +1,338 @@
+use super::executor::{self, SyncPoint};
+use super::soak::parse_duration;
+use super::{Cmd, ExecStatus, ResourceEnv, Test, TestResult};
+use libparser::Perf;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const DEFAULT_BENCH_DURATION: Duration = Duration::from_secs(1);
+
+/// Benchmark of a test, sweeping the number of threads running its cmds.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct BenchConfig {
+    // thread counts to run one after another, such as [1, 2, 4, 8]
+    pub threads: Vec<usize>,
+    // how long every thread count runs, default is 1s
+    #[serde(default)]
+    pub duration: Option<String>,
+    // or how many times every thread runs the cmds
+    #[serde(default)]
+    pub iterations: Option<usize>,
+    // csv file written with the results of this test
+    #[serde(default)]
+    pub csv: Option<String>,
+}
+
+impl BenchConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.threads.is_empty() || self.threads.contains(&0) {
+            return Err("bench threads should be a list of positive numbers".to_string());
+        }
+        if self.duration.is_some() && self.iterations.is_some() {
+            return Err("bench duration and iterations are mutually exclusive".to_string());
+        }
+        if let Some(ref duration) = self.duration {
+            parse_duration(duration)?;
+        }
+        if self.iterations == Some(0) {
+            return Err("bench iterations should be positive".to_string());
+        }
+        Ok(())
+    }
+
+    fn stop(&self) -> BenchStop {
+        match self.iterations {
+            Some(iterations) => BenchStop::Iterations(iterations),
+            None => BenchStop::Duration(
+                self.duration
+                    .as_deref()
+                    .and_then(|duration| parse_duration(duration).ok())
+                    .unwrap_or(DEFAULT_BENCH_DURATION),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BenchStop {
+    Duration(Duration),
+    Iterations(usize),
+}
+
+impl std::fmt::Display for BenchStop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BenchStop::Duration(duration) => write!(f, "{:?}", duration),
+            BenchStop::Iterations(iterations) => write!(f, "{} iterations", iterations),
+        }
+    }
+}
+
+// what one thread measured
+#[derive(Debug, Default)]
+struct ThreadSample {
+    // nanoseconds of every passed run of the cmds
+    latencies: Vec<u64>,
+    failed: usize,
+    start: Option<Instant>,
+    end: Option<Instant>,
+}
+
+/// Throughput and latency of one thread count.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchRow {
+    pub threads: usize,
+    pub ops: usize,
+    pub failed: usize,
+    pub ops_per_sec: f64,
+    // latencies in microseconds
+    pub mean_us: f64,
+    pub p50_us: f64,
+    pub p90_us: f64,
+    pub p99_us: f64,
+    pub max_us: f64,
+}
+
+// nearest rank percentile of sorted values
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl BenchRow {
+    fn new(threads: usize, mut latencies: Vec<u64>, failed: usize, elapsed: Duration) -> Self {
+        latencies.sort_unstable();
+        let ops = latencies.len();
+        let us = |ns: u64| ns as f64 / 1000.0;
+        let mean = if ops == 0 {
+            0.0
+        } else {
+            latencies.iter().sum::<u64>() as f64 / ops as f64 / 1000.0
+        };
+        let secs = elapsed.as_secs_f64();
+        BenchRow {
+            threads,
+            ops,
+            failed,
+            ops_per_sec: if secs > 0.0 { ops as f64 / secs } else { 0.0 },
+            mean_us: mean,
+            p50_us: us(percentile(&latencies, 50.0)),
+            p90_us: us(percentile(&latencies, 90.0)),
+            p99_us: us(percentile(&latencies, 99.0)),
+            max_us: us(latencies.last().copied().unwrap_or(0)),
+        }
+    }
+
+    const CSV_HEADER: &'static str = "test,threads,ops,failed,ops_per_sec,mean_us,p50_us,p90_us,p99_us,max_us";
+
+    fn csv(&self, test: &str) -> String {
+        format!(
+            "{},{},{},{},{:.2},{:.3},{:.3},{:.3},{:.3},{:.3}",
+            test, self.threads, self.ops, self.failed, self.ops_per_sec,
+            self.mean_us, self.p50_us, self.p90_us, self.p99_us, self.max_us
+        )
+    }
+}
+
+fn print_table(name: &str, stop: BenchStop, rows: &[BenchRow]) {
+    println!("Benchmark {} ({} per thread count):", name, stop);
+    println!(
+        "{:>8} {:>12} {:>8} {:>14} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "threads", "ops", "failed", "ops/sec", "mean(us)", "p50(us)", "p90(us)", "p99(us)", "max(us)"
+    );
+    for row in rows {
+        println!(
+            "{:>8} {:>12} {:>8} {:>14.2} {:>12.3} {:>12.3} {:>12.3} {:>12.3} {:>12.3}",
+            row.threads, row.ops, row.failed, row.ops_per_sec,
+            row.mean_us, row.p50_us, row.p90_us, row.p99_us, row.max_us
+        );
+    }
+}
+
+/// Append the rows to a csv file, with the header when the file is empty.
+pub fn write_csv(path: &str, name: &str, rows: &[BenchRow]) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        writeln!(file, "{}", BenchRow::CSV_HEADER)?;
+    }
+    for row in rows {
+        writeln!(file, "{}", row.csv(name))?;
+    }
+    Ok(())
+}
+
+// run the cmds once, barriers mean nothing here
+fn run_cmds_once(test: &Test, cmds: &[Cmd]) -> ExecStatus {
+    let mut final_status = ExecStatus::Passed;
+    for cmd in cmds.iter().filter(|cmd| !cmd.is_barrier()) {
+        let status = cmd.run().unwrap_or_else(|e| {
+            error!("execute cmd {} failed! Error: {}", cmd.opfunc, e);
+            ExecStatus::Failed
+        });
+        match status {
+            ExecStatus::Passed => {}
+            ExecStatus::Failed if !test.break_if_fail => final_status = ExecStatus::Failed,
+            status => return status,
+        }
+    }
+    final_status
+}
+
+fn bench_thread(test: &Test, stop: BenchStop, sync: &SyncPoint) -> ThreadSample {
+    let thread_env = ResourceEnv::get_instance()
+        .and_then(|instance| instance.read().unwrap().thread_env.clone());
+    if let Some(ref thread_env) = thread_env {
+        thread_env.apply_env_init();
+    }
+    // the env cmds run once around the timed runs of the test's own cmds
+    let (init, rest) = test.cmds.split_at(test.env_init_len.min(test.cmds.len()));
+    let (cmds, exit) = rest.split_at(rest.len().saturating_sub(test.env_exit_len));
+    let mut sample = ThreadSample::default();
+    let ready = match run_cmds_once(test, init) {
+        ExecStatus::Passed => true,
+        status => {
+            error!("Env init of benchmark {} {:?}, stop this thread.", test.name, status);
+            sample.failed += 1;
+            false
+        }
+    };
+    sync.wait();
+    let start = Instant::now();
+    let mut iterations = 0;
+    loop {
+        let done = match stop {
+            BenchStop::Duration(duration) => start.elapsed() >= duration,
+            BenchStop::Iterations(max) => iterations >= max,
+        };
+        if !ready || done || ResourceEnv::should_stop() {
+            break;
+        }
+        iterations += 1;
+        let mut perf = Perf::new();
+        let status = run_cmds_once(test, cmds);
+        perf.record();
+        match status {
+            ExecStatus::Passed => sample.latencies.push(perf.duration().as_nanos() as u64),
+            ExecStatus::Failed => sample.failed += 1,
+            ExecStatus::Skipped => {
+                warn!("Benchmark {} skipped by a cmd, stop this thread.", test.name);
+                break;
+            }
+        }
+    }
+    sample.start = Some(start);
+    sample.end = Some(Instant::now());
+    if run_cmds_once(test, exit) != ExecStatus::Passed {
+        warn!("Env exit of benchmark {} failed.", test.name);
+    }
+    if let Some(ref thread_env) = thread_env {
+        thread_env.apply_env_exit();
+    }
+    sample
+}
+
+// run the test on `threads` threads started together
+fn bench_threads(test: &Test, threads: usize, stop: BenchStop) -> BenchRow {
+    let sync = SyncPoint::new(threads);
+    let ids: Vec<usize> = (0..threads).collect();
+    let samples = executor::spawn_map(&ids, |_| bench_thread(test, stop, &sync));
+
+    let start = samples.iter().filter_map(|sample| sample.start).min();
+    let end = samples.iter().filter_map(|sample| sample.end).max();
+    let elapsed = match (start, end) {
+        (Some(start), Some(end)) => end - start,
+        _ => Duration::ZERO,
+    };
+    let failed = samples.iter().map(|sample| sample.failed).sum();
+    let latencies = samples.into_iter().flat_map(|sample| sample.latencies).collect();
+    BenchRow::new(threads, latencies, failed, elapsed)
+}
+
+/// Run the benchmark of `test` for every thread count, print the table and
+/// write the csv files. A thread count passes when none of its runs failed.
+pub fn run_bench(test: &Test, bench: &BenchConfig) -> TestResult {
+    let stop = bench.stop();
+    let mut result = TestResult::default();
+    let mut rows = Vec::new();
+    for &threads in &bench.threads {
+        if ResourceEnv::should_stop() {
+            result.add_not_run(test.name.clone(), 1);
+            continue;
+        }
+        info!("Benchmark {} with {} threads for {}.", test.name, threads, stop);
+        let row = bench_threads(test, threads, stop);
+        if row.failed > 0 {
+            error!("Benchmark {} with {} threads, {} runs failed!", test.name, threads, row.failed);
+            result.failed += 1;
+            ResourceEnv::record_failure();
+        } else {
+            result.passed += 1;
+        }
+        rows.push(row);
+    }
+    print_table(&test.name, stop, &rows);
+
+    let csv_files = bench.csv.iter().chain(ResourceEnv::settings().bench_csv.iter()).cloned().collect::<Vec<_>>();
+    for path in csv_files {
+        if let Err(e) = write_csv(&path, &test.name, &rows) {
+            error!("Failed to write benchmark csv {}: {}", path, e);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_row() {
+        let latencies: Vec<u64> = (1..=100).map(|i| i * 1000).collect();
+        let row = BenchRow::new(4, latencies, 2, Duration::from_secs(2));
+        assert_eq!(row.ops, 100);
+        assert_eq!(row.ops_per_sec, 50.0);
+        assert_eq!(row.mean_us, 50.5);
+        assert_eq!((row.p50_us, row.p90_us, row.p99_us, row.max_us), (50.0, 90.0, 99.0, 100.0));
+        assert_eq!(row.csv("t"), "t,4,100,2,50.00,50.500,50.000,90.000,99.000,100.000");
+
+        let empty = BenchRow::new(1, Vec::new(), 0, Duration::ZERO);
+        assert_eq!((empty.ops_per_sec, empty.p99_us), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_bench_config() {
+        let bench: BenchConfig = toml::from_str("threads = [1, 2, 4]\niterations = 10").unwrap();
+        assert!(bench.validate().is_ok());
+        assert!(matches!(bench.stop(), BenchStop::Iterations(10)));
+
+        let bench: BenchConfig = toml::from_str("threads = [1]").unwrap();
+        assert!(matches!(bench.stop(), BenchStop::Duration(d) if d == DEFAULT_BENCH_DURATION));
+
+        for invalid in ["threads = []", "threads = [0]", "threads = [1]\nduration = \"1s\"\niterations = 1", "threads = [1]\nduration = \"x\""] {
+            let bench: BenchConfig = toml::from_str(invalid).unwrap();
+            assert!(bench.validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let path = std::env::temp_dir().join(format!("hitest_bench_{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path = path.to_str().unwrap();
+        let row = BenchRow::new(1, vec![1000], 0, Duration::from_secs(1));
+        write_csv(path, "a", std::slice::from_ref(&row)).unwrap();
+        write_csv(path, "b", &[row]).unwrap();
+        let content = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], BenchRow::CSV_HEADER);
+        assert!(lines[2].starts_with("b,1,1,0,1.00,"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
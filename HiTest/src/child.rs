@@ -0,0 +1,289 @@
+use nix::{
+    errno::Errno,
+    fcntl::{fcntl, FcntlArg, FdFlag, OFlag},
+    sys::signal::{kill, SIGKILL},
+    sys::wait::{waitpid, WaitStatus},
+    unistd::{close, dup2, pipe2, read, write, Pid},
+};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+
+// the write end of the report pipe in a child process, -1 when not reporting
+static REPORT_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// A pipe set up before fork to collect the output of the child process,
+/// its stderr or a result it writes.
+pub struct ChildPipe {
+    read_fd: RawFd,
+    write_fd: RawFd,
+}
+
+impl ChildPipe {
+    pub fn new() -> nix::Result<Self> {
+        // not inherited by other exec children started meanwhile
+        let (read_fd, write_fd) = pipe2(OFlag::O_CLOEXEC)?;
+        Ok(ChildPipe { read_fd, write_fd })
+    }
+
+    /// Called in the child, keep the write end only.
+    pub fn into_writer(self) -> RawFd {
+        let _ = close(self.read_fd);
+        self.write_fd
+    }
+
+    /// Called in the child, redirect stderr to the pipe.
+    pub fn redirect_stderr(&self) {
+        let _ = close(self.read_fd);
+        let _ = dup2(self.write_fd, nix::libc::STDERR_FILENO);
+        let _ = close(self.write_fd);
+    }
+
+    /// The write end as the stderr of a spawned process.
+    pub fn stdio(&self) -> nix::Result<Stdio> {
+        let fd = fcntl(self.write_fd, FcntlArg::F_DUPFD_CLOEXEC(0))?;
+        Ok(unsafe { Stdio::from_raw_fd(fd) })
+    }
+
+    /// Called in the child, send what `report_check_failed` tells to the pipe.
+    pub fn set_as_report(&self) {
+        let _ = close(self.read_fd);
+        set_report_fd(self.write_fd);
+    }
+
+    /// Keep the write end open in the process `command` execs, returns its fd
+    /// there to give to `set_report_fd`.
+    pub fn pass_to(&self, command: &mut Command) -> RawFd {
+        let fd = self.write_fd;
+        unsafe {
+            command.pre_exec(move || {
+                fcntl(fd, FcntlArg::F_SETFD(FdFlag::empty()))?;
+                Ok(())
+            });
+        }
+        fd
+    }
+
+    /// Called in the parent, keep the read end only and make it non-blocking.
+    fn into_reader(self) -> RawFd {
+        let _ = close(self.write_fd);
+        let _ = fcntl(self.read_fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK));
+        self.read_fd
+    }
+}
+
+/// Called in an exec child, report to the pipe `fd` given by `ChildPipe::pass_to`.
+pub fn set_report_fd(fd: RawFd) {
+    REPORT_FD.store(fd, Ordering::SeqCst);
+}
+
+// a line for the parent, nothing when the process is not a reporting child
+fn report(line: &str) {
+    let fd = REPORT_FD.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let _ = write(fd, format!("{}\n", line).as_bytes());
+    }
+}
+
+/// Tell the parent the opfunc and actual return value of a failed check.
+pub fn report_check_failed(opfunc: &str, actual: i64) {
+    report(&format!("check_failed {} {}", opfunc, actual));
+}
+
+/// Tell the parent the cmds of the test all returned, an exit before it is
+/// not the test passing.
+pub fn report_finished() {
+    report("finished");
+}
+
+/// What a child process told its parent through the report pipe.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChildReport {
+    /// The opfunc and actual return value of the first failed check.
+    pub check: Option<(String, i64)>,
+    pub finished: bool,
+}
+
+impl ChildReport {
+    fn parse(text: &str) -> Self {
+        let mut report = ChildReport::default();
+        for line in text.lines() {
+            if let Some(check) = line.strip_prefix("check_failed ") {
+                let check = check
+                    .rsplit_once(' ')
+                    .and_then(|(opfunc, actual)| Some((opfunc.to_string(), actual.parse().ok()?)));
+                report.check = report.check.or(check);
+            } else if line == "finished" {
+                report.finished = true;
+            }
+        }
+        report
+    }
+}
+
+// waitpid without blocking, giving the cpu time the child used when it ended
+fn try_wait(child: Pid) -> nix::Result<(WaitStatus, Duration)> {
+    let mut status = 0;
+    let mut usage: nix::libc::rusage = unsafe { std::mem::zeroed() };
+    let pid = Errno::result(unsafe { nix::libc::wait4(child.as_raw(), &mut status, nix::libc::WNOHANG, &mut usage) })?;
+    if pid == 0 {
+        return Ok((WaitStatus::StillAlive, Duration::ZERO));
+    }
+    let time = |t: nix::libc::timeval| Duration::new(t.tv_sec as u64, t.tv_usec as u32 * 1000);
+    Ok((WaitStatus::from_raw(Pid::from_raw(pid), status)?, time(usage.ru_utime) + time(usage.ru_stime)))
+}
+
+// read what is available now, the child may block on a full pipe otherwise
+fn drain(fd: RawFd, out: &mut Vec<u8>) {
+    let mut buf = [0u8; 4096];
+    loop {
+        match read(fd, &mut buf) {
+            Ok(0) => break,
+            Ok(n) => out.extend_from_slice(&buf[..n]),
+            Err(Errno::EINTR) => continue,
+            Err(_) => break,
+        }
+    }
+}
+
+/// How a child process finished.
+pub struct ChildExit {
+    /// None when the child is killed because of timeout.
+    pub status: Option<WaitStatus>,
+    pub output: String,
+    pub report: ChildReport,
+    /// User and system cpu time of the child, zero when it is killed because of timeout.
+    pub cpu_time: Duration,
+}
+
+/// Wait for the child to finish, kill it after `timeout` if given.
+pub fn wait_child(
+    child: Pid,
+    timeout: Option<Duration>,
+    output: Option<ChildPipe>,
+) -> nix::Result<ChildExit> {
+    wait_child_with_report(child, timeout, output, None)
+}
+
+/// `wait_child` reading what the child reports to `report` too.
+pub fn wait_child_with_report(
+    child: Pid,
+    timeout: Option<Duration>,
+    output: Option<ChildPipe>,
+    report: Option<ChildPipe>,
+) -> nix::Result<ChildExit> {
+    let readers: Vec<(RawFd, bool)> = output
+        .map(|pipe| (pipe.into_reader(), false))
+        .into_iter()
+        .chain(report.map(|pipe| (pipe.into_reader(), true)))
+        .collect();
+    let mut buf = Vec::new();
+    let mut report_buf = Vec::new();
+    let drain_all = |buf: &mut Vec<u8>, report_buf: &mut Vec<u8>| {
+        for &(fd, is_report) in &readers {
+            drain(fd, if is_report { &mut *report_buf } else { &mut *buf });
+        }
+    };
+    let close_all = || {
+        for &(fd, _) in &readers {
+            let _ = close(fd);
+        }
+    };
+    let start = Instant::now();
+
+    let mut cpu_time = Duration::ZERO;
+    let status = loop {
+        drain_all(&mut buf, &mut report_buf);
+        match try_wait(child) {
+            Ok((WaitStatus::StillAlive, _)) => {
+                if timeout.is_some_and(|timeout| start.elapsed() > timeout) {
+                    let _ = kill(child, SIGKILL);
+                    let _ = waitpid(child, None);
+                    break None;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Ok((status, time)) => {
+                cpu_time = time;
+                break Some(status);
+            }
+            Err(Errno::EINTR) => continue,
+            Err(e) => {
+                close_all();
+                return Err(e);
+            }
+        }
+    };
+
+    drain_all(&mut buf, &mut report_buf);
+    close_all();
+    Ok(ChildExit {
+        status,
+        output: String::from_utf8_lossy(&buf).into_owned(),
+        report: ChildReport::parse(&String::from_utf8_lossy(&report_buf)),
+        cpu_time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::unistd::{fork, ForkResult};
+
+    fn spawn(pipe: Option<&ChildPipe>, sleep_ms: u64, code: i32) -> Pid {
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => {
+                // not to the stderr of the test run, it looks like a real error there
+                if let Some(pipe) = pipe {
+                    pipe.redirect_stderr();
+                    let _ = write(nix::libc::STDERR_FILENO, b"double free detected\n");
+                }
+                std::thread::sleep(Duration::from_millis(sleep_ms));
+                unsafe { nix::libc::_exit(code) };
+            }
+            ForkResult::Parent { child } => child,
+        }
+    }
+
+    #[test]
+    fn test_wait_child_captures_stderr() {
+        let pipe = ChildPipe::new().unwrap();
+        let child = spawn(Some(&pipe), 0, 3);
+        let res = wait_child(child, Some(Duration::from_secs(5)), Some(pipe)).unwrap();
+        assert!(matches!(res.status, Some(WaitStatus::Exited(_, 3))));
+        assert_eq!(res.output, "double free detected\n");
+    }
+
+    #[test]
+    fn test_wait_child_timeout() {
+        let child = spawn(None, 5000, 0);
+        let start = Instant::now();
+        let res = wait_child(child, Some(Duration::from_millis(100)), None).unwrap();
+        assert!(res.status.is_none());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_wait_child_report() {
+        let pipe = ChildPipe::new().unwrap();
+        let report = ChildPipe::new().unwrap();
+        let child = match unsafe { fork() }.unwrap() {
+            ForkResult::Child => {
+                report.set_as_report();
+                report_check_failed("Call_read32", -14);
+                report_check_failed("Call_free", 1);
+                report_finished();
+                unsafe { nix::libc::_exit(1) };
+            }
+            ForkResult::Parent { child } => child,
+        };
+        let res = wait_child_with_report(child, Some(Duration::from_secs(5)), Some(pipe), Some(report)).unwrap();
+        assert!(res.output.is_empty());
+        // the first failed check is kept
+        assert_eq!(res.report.check, Some(("Call_read32".to_string(), -14)));
+        assert!(res.report.finished);
+        assert_eq!(ChildReport::parse("check_failed Call_f x\n"), ChildReport::default());
+    }
+}
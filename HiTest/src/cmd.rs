@@ -1,7 +1,8 @@
 use super::condition::Condition;
 use libparser::LibParse;
 use log::{debug, error, info};
-use serde::Deserialize;
+use nix::errno::Errno;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
 
@@ -14,7 +15,21 @@ pub enum ExecStatus {
     Skipped,
 }
 
-#[derive(Debug, Deserialize, Clone, Default)]
+/// How a cmd ran, see `Cmd::run_with_ret`.
+#[derive(Debug, Clone, Copy)]
+pub struct CmdRet {
+    pub status: ExecStatus,
+    // the actual return value
+    pub ret: i64,
+    // the errno set by the call itself, None when it set none
+    pub errno: Option<Errno>,
+}
+
+/// The pseudo cmd synchronizing the threads of a test, see `SyncPoint`.
+pub const BARRIER_OPFUNC: &str = "barrier";
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(try_from = "CmdDef")]
 pub struct Cmd {
     pub opfunc: String,
     #[serde(flatten)]
@@ -24,6 +39,46 @@ pub struct Cmd {
     pub perf: bool,
 }
 
+// a barrier has no return value to check, other cmds must give a condition
+#[derive(Deserialize)]
+struct CmdDef {
+    opfunc: String,
+    #[serde(flatten)]
+    condition: Option<Condition>,
+    #[serde(default)]
+    args: Option<Vec<String>>,
+    #[serde(default)]
+    perf: bool,
+}
+
+impl TryFrom<CmdDef> for Cmd {
+    type Error = String;
+
+    fn try_from(def: CmdDef) -> Result<Self, Self::Error> {
+        let condition = match def.condition {
+            Some(condition) => condition,
+            None if def.opfunc == BARRIER_OPFUNC => Condition::default(),
+            None => {
+                return Err(format!(
+                    "cmd '{}' needs exactly one of 'expect_eq' or 'expect_ne'",
+                    def.opfunc
+                ))
+            }
+        };
+        let args = match def.args {
+            Some(args) => args,
+            None if def.opfunc == BARRIER_OPFUNC => Vec::new(),
+            None => return Err(format!("cmd '{}' misses field 'args'", def.opfunc)),
+        };
+        Ok(Cmd {
+            opfunc: def.opfunc,
+            condition,
+            args,
+            perf: def.perf,
+        })
+    }
+}
+
 impl fmt::Display for Cmd {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}(", self.opfunc)?;
@@ -49,35 +104,50 @@ impl fmt::Display for Cmd {
 }
 
 impl Cmd {
+    pub fn barrier() -> Self {
+        Cmd {
+            opfunc: BARRIER_OPFUNC.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_barrier(&self) -> bool {
+        self.opfunc == BARRIER_OPFUNC
+    }
+
     fn parse_value(s: &str) -> Result<i64, Box<dyn Error>> {
         let actual_s = if s.starts_with('!') { &s[1..] } else { s };
-        if actual_s.starts_with("0x") || actual_s.starts_with("0X") {
-            i64::from_str_radix(&actual_s[2..], 16).map_err(|e| {
-                error!("Failed to parse hexadecimal value: {}", s);
-                e.into()
-            })
-        } else {
-            actual_s.parse::<i64>().map_err(|e| {
-                error!("Failed to parse decimal value: {}", s);
-                e.into()
-            })
-        }
+        libparser::parse_num(actual_s).ok_or_else(|| {
+            error!("Failed to parse value: {}", s);
+            format!("invalid number '{}'", s).into()
+        })
     }
 
     pub fn run(&self) -> Result<ExecStatus, Box<dyn Error>> {
+        self.run_with_ret().map(|cmd_ret| cmd_ret.status)
+    }
+
+    /// `run` giving the actual return value and errno of the cmd too.
+    pub fn run_with_ret(&self) -> Result<CmdRet, Box<dyn Error>> {
         debug!("start executing cmd {} ", self);
 
         let lib_parser = LibParse::get_instance()?.read().unwrap();
-        let ret: i64 = if self.perf {
+        Errno::clear();
+        let (ret, perf) = if self.perf {
             let (ans, perf) = lib_parser.execute_with_perf(self.opfunc.clone(), &self.args)?;
-            info!("cmd '{}{:?}' executed cost {}", self.opfunc, &self.args, perf);
-            ans
+            (ans, Some(perf))
         } else {
-            lib_parser.execute(self.opfunc.clone(), &self.args)?
+            (lib_parser.execute(self.opfunc.clone(), &self.args)?, None)
         };
+        // read before logging, which may set errno too
+        let errno = Some(Errno::last()).filter(|errno| *errno != Errno::UnknownErrno);
+        let cmd_ret = |status| CmdRet { status, ret, errno };
+        if let Some(perf) = perf {
+            info!("cmd '{}{:?}' executed cost {}", self.opfunc, &self.args, perf);
+        }
         if ret == TEST_RET_SKIP {
             debug!("cmd '{}' returned SKIP code {}. Case will be skipped.", self.opfunc, ret);
-            return Ok(ExecStatus::Skipped);
+            return Ok(cmd_ret(ExecStatus::Skipped));
         }
         let (expected, operator, is_success) = match &self.condition {
             Condition::Eq(v) => {
@@ -101,10 +171,10 @@ impl Cmd {
 
         if !is_success {
             error!("{} validate failed", message);
-            Ok(ExecStatus::Failed)
+            Ok(cmd_ret(ExecStatus::Failed))
         } else {
             debug!("{} validate succeeded", message);
-            Ok(ExecStatus::Passed)
+            Ok(cmd_ret(ExecStatus::Passed))
         }
 
     }
@@ -148,4 +218,29 @@ mod tests {
         let cmd3: Cmd = toml::from_str(cmd_str3).unwrap();
         assert!(matches!(cmd3.condition, Condition::Ne(_)));
     }
+
+    #[test]
+    fn test_barrier_cmd() {
+        let barrier: Cmd = toml::from_str(r#"opfunc = "barrier""#).unwrap();
+        assert!(barrier.is_barrier());
+        assert!(barrier.args.is_empty());
+
+        let no_condition = r#"
+            opfunc = "Call_malloc"
+            args = ["len=8", "mem_idx=1"]
+        "#;
+        assert!(toml::from_str::<Cmd>(no_condition).is_err());
+        let both = r#"
+            opfunc = "Call_malloc"
+            expect_eq = 0
+            expect_ne = 1
+            args = ["len=8", "mem_idx=1"]
+        "#;
+        assert!(toml::from_str::<Cmd>(both).is_err());
+        let no_args = r#"
+            opfunc = "Call_malloc"
+            expect_eq = 0
+        "#;
+        assert!(toml::from_str::<Cmd>(no_args).is_err());
+    }
 }
@@ -1,8 +1,9 @@
-use crate::{ResourceEnv, Test, TestResult};
+use crate::executor::{self, SyncPoint};
+use crate::soak::{parse_duration, run_soak};
+use crate::{ResourceEnv, TagFilter, Test, TestResult};
+use std::sync::Arc;
 use log::{debug, error, info, warn};
 use rand::seq::SliceRandom;
-use rand::thread_rng;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize, Clone)]
@@ -10,19 +11,64 @@ pub struct ConcurrencyGroup {
     tests: Vec<String>,
     #[serde(default = "default_name")]
     name: String,
+    #[serde(default)]
+    process_num: Option<usize>,
+    #[serde(default)]
+    sync_start: bool,
+    // loop the group until this much time passed, such as "8h"
+    #[serde(default)]
+    duration: Option<String>,
 }
 fn default_name() -> String {
     String::from("default_group")
 }
 
 impl ConcurrencyGroup {
+    /// Run the group, again and again until its duration expires when one is given.
     pub fn run(&self, tests: &Vec<Test>) -> TestResult {
+        match self.duration.as_deref().map(parse_duration) {
+            Some(Ok(duration)) => {
+                let name = format!("concurrency group {}", self.name);
+                let interval = ResourceEnv::settings().soak_interval;
+                run_soak(&name, duration, interval, || self.run_once(tests)).aggregate()
+            }
+            _ => self.run_once(tests),
+        }
+    }
+
+    /// Check the duration of the group.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ref duration) = self.duration {
+            parse_duration(duration)
+                .map_err(|e| format!("Invalid duration of concurrency group '{}': {}", self.name, e))?;
+        }
+        Ok(())
+    }
+
+    fn run_once(&self, tests: &Vec<Test>) -> TestResult {
+        let process_num = self.process_num.unwrap_or(1);
+        if process_num > 1 && !ResourceEnv::should_stop() {
+            #[cfg(unix)]
+            {
+                let tag_filter = ResourceEnv::settings().tag_filter;
+                let expected = tests
+                    .iter()
+                    .filter(|test| self.tests.contains(&test.name))
+                    .map(|test| test.case_count(&tag_filter))
+                    .sum();
+                return crate::process::run_in_processes(&self.name, process_num, expected, || {
+                    self.run_in_process(tests)
+                });
+            }
+            #[cfg(not(unix))]
+            warn!("process_num of concurrency group {} is not supported on this platform, run in one process.", self.name);
+        }
+        self.run_in_process(tests)
+    }
+
+    fn run_in_process(&self, tests: &Vec<Test>) -> TestResult {
         if self.tests.is_empty() {
-            return TestResult {
-                passed: 0,
-                failed: 0,
-                skipped:0,
-            };
+            return TestResult::default();
         }
 
         let mut test_cases: Vec<Test> = Vec::new();
@@ -30,64 +76,113 @@ impl ConcurrencyGroup {
             if self.tests.contains(&original_test.name) {
                 let mut cloned_test = original_test.clone();
                 cloned_test.name = format!("{}_{}", self.name, original_test.name);
+                // the members start and stop with the group
+                cloned_test.duration = None;
+                cloned_test.bench = None;
                 test_cases.push(cloned_test);
             }
         }
 
         if test_cases.is_empty() {
-            return TestResult {
-                passed: 0,
-                failed: 0,
-                skipped:0,
-            };
+            return TestResult::default();
         }
 
         debug!(
             "Concurrency Group {} parallel running test cases: {:#?}",
             self.name, self.tests
         );
-        let max_thread = {
-            let res_env = ResourceEnv::get_instance().unwrap().read().unwrap();
-            res_env.max_threads
-        };
-        let results: Vec<_> = if let Some(max_thread) = max_thread{
+        let settings = ResourceEnv::settings();
+        if self.sync_start {
+            // every thread of every member starts together, no grouping
+            let parties = test_cases
+                .iter()
+                .filter(|test| test.disabled.is_none())
+                .map(|test| test.case_count(&settings.tag_filter))
+                .sum();
+            let sync = Arc::new(SyncPoint::new(parties));
+            for test in test_cases.iter_mut() {
+                test.sync = Some(sync.clone());
+            }
+            info!("Concurrency Group {} starts {} threads together!", self.name, parties);
+            let results = executor::spawn_map(&test_cases, |test| test.run());
+            return self.summarize(results);
+        }
+        let results: Vec<_> = if let Some(max_thread) = settings.max_threads {
             if test_cases.len() > max_thread {
                 warn!("Concurrency Group {} total test cases is {}, but max-threads is {} thread, will be grouped.",
                 self.name, test_cases.len(), max_thread);
 
-                let mut rng = thread_rng();
+                let mut rng = settings.rng(&self.name);
                 let mut shuffled_tests = test_cases;
                 shuffled_tests.shuffle(&mut rng);
 
                 let mut results = Vec::new();
                 for chunk in shuffled_tests.chunks(max_thread) {
-                    let chunk_results: Vec<_> = chunk.into_par_iter().map(|test| test.run()).collect();
-                    results.extend(chunk_results);
+                    results.extend(executor::par_map(chunk, |test| test.run()));
                 }
                 results
             } else {
-                test_cases.into_par_iter().map(|test| test.run()).collect()
+                executor::par_map(&test_cases, |test| test.run())
             }
         } else {
-            test_cases.into_par_iter().map(|test| test.run()).collect()
+            executor::par_map(&test_cases, |test| test.run())
         };
 
-        let passed = results.iter().map(|r| r.passed).sum();
-        let failed = results.iter().map(|r| r.failed).sum();
-        let skipped = results.iter().map(|r| r.skipped).sum();
-        if failed == 0 {
+        self.summarize(results)
+    }
+
+    fn summarize(&self, results: Vec<TestResult>) -> TestResult {
+        let mut group_result = TestResult::default();
+        for res in &results {
+            group_result.merge(res);
+        }
+        if group_result.failed == 0 {
             info!(
-                "Parallel execute concurrency Group {} with {} thread, all passed!\n",
-                self.name, failed
+                "Parallel execute concurrency Group {} with {} thread, all passed! {}\n",
+                self.name, group_result.total(), group_result
             );
         } else {
             error!(
-                "Parallel execute concurrency Group {} with {} thread, {} passed!\n",
-                self.name, failed + passed, passed
+                "Parallel execute concurrency Group {} with {} thread, {} passed! {}\n",
+                self.name, group_result.total(), group_result.passed, group_result
             );
         }
 
-        TestResult { passed, failed, skipped }
+        group_result
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Report the members of this group as not run, used when the run stops early.
+    pub fn not_run(&self, tests: &[Test], tag_filter: &TagFilter) -> TestResult {
+        let mut res = TestResult::default();
+        for test in tests.iter().filter(|test| self.tests.contains(&test.name)) {
+            let mut test = test.clone();
+            test.name = format!("{}_{}", self.name, test.name);
+            res.merge(&test.not_run(tag_filter));
+        }
+        res
+    }
+
+    /// Names of the sub tests this group would run.
+    pub fn list(&self, tests: &[Test], tag_filter: &TagFilter) -> Vec<String> {
+        tests
+            .iter()
+            .filter(|test| self.tests.contains(&test.name))
+            .flat_map(|test| {
+                let mut test = test.clone();
+                test.name = format!("{}_{}", self.name, test.name);
+                test.expand(tag_filter)
+            })
+            .map(|test| test.name)
+            .collect()
+    }
+
+    /// Whether `test` starts together with the other members of this group.
+    pub fn syncs(&self, test: &str) -> bool {
+        self.sync_start && self.tests.iter().any(|name| name == test)
     }
 
     pub fn record_test(&self, tests: &mut Vec<String>) {
@@ -1,4 +1,4 @@
-use serde::{de::Error as DError, Deserialize, Deserializer};
+use serde::{de::Error as DError, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Condition {
@@ -44,6 +44,21 @@ impl<'de> Deserialize<'de> for Condition {
     }
 }
 
+// the reverse of deserialize, flattened into Cmd as expect_eq or expect_ne
+impl Serialize for Condition {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Condition", 1)?;
+        match self {
+            Condition::Eq(value) => state.serialize_field("expect_eq", value)?,
+            Condition::Ne(value) => state.serialize_field("expect_ne", value)?,
+        }
+        state.end()
+    }
+}
+
 impl Default for Condition {
     fn default() -> Self {
         Condition::Eq(String::new())
@@ -1,10 +1,17 @@
+use crate::env::DEFAULT_DEATH_TIMEOUT_MS;
 use crate::input::ArgValue;
+use crate::soak::{parse_duration, run_soak, DEFAULT_SOAK_INTERVAL};
+#[cfg(unix)]
+use crate::minimize::minimize;
 
-use super::{ConcurrencyGroup, Env, ResourceEnv, Test};
+use super::{Cmd, ConcurrencyGroup, FuzzCampaign, Vars, DeathMode, Env, Executor, ResourceLimits, NameFilter, NamePattern, ResourceEnv, RunSettings, TagFilter, Test, TestResult};
 use log::{debug, info, warn};
+use rand::seq::SliceRandom;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 #[derive(Debug, Deserialize, Default)]
@@ -19,11 +26,51 @@ pub struct Config {
     process_env: Option<Env>,
     #[serde(default)]
     shared_inputs: HashMap<String, HashMap<String, ArgValue>>,
+    // may be left out by a config of fuzz campaigns only
+    #[serde(default)]
     tests: Vec<Test>,
     #[serde(default = "default_false")]
     pub default_serial: bool,
     #[serde(default)]
     pub debug_test: Option<String>,
+    #[serde(default)]
+    pub retries: usize,
+    #[serde(default)]
+    pub max_failures: Option<usize>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default = "default_false")]
+    pub shuffle: bool,
+    #[serde(default)]
+    pub death_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub death_mode: DeathMode,
+    #[serde(default = "default_false")]
+    pub isolate: bool,
+    #[serde(default)]
+    limits: ResourceLimits,
+    #[serde(default)]
+    pub executor: Executor,
+    #[serde(default)]
+    pub soak: Option<String>,
+    #[serde(default)]
+    pub soak_interval: Option<String>,
+    #[serde(default)]
+    pub bench_csv: Option<String>,
+    #[serde(default)]
+    fuzz: Vec<FuzzCampaign>,
+    // run the fuzz campaigns instead of the test cases
+    #[serde(skip)]
+    pub fuzz_mode: bool,
+    #[serde(skip)]
+    pub libs_cfg: String,
+    #[serde(skip)]
+    pub tag_filter: TagFilter,
+    #[serde(skip)]
+    pub name_filter: NameFilter,
+    // the -D and --vars values applied by apply_vars
+    #[serde(skip)]
+    vars: Vars,
 }
 fn default_false() -> bool {
     false
@@ -35,6 +82,15 @@ impl Config {
         if global_envs > 1 {
             return Err("Only one global env (with empty tests) is allowed".to_string());
         }
+        for duration in self.soak.iter().chain(self.soak_interval.iter()) {
+            parse_duration(duration)?;
+        }
+        for concurrency in self.concurrences.iter().flatten() {
+            concurrency.validate()?;
+        }
+        for fuzz in &self.fuzz {
+            fuzz.validate()?;
+        }
 
         for test in &self.tests {
             let applied_envs = self
@@ -48,6 +104,12 @@ impl Config {
                     test.name
                 ));
             }
+            // the inputs are checked with the shared inputs they refer to
+            let mut resolved = test.clone();
+            resolved
+                .resolve_refs(&self.shared_inputs)
+                .and_then(|_| resolved.validate())
+                .map_err(|e| e.to_string())?;
         }
         Ok(())
     }
@@ -61,6 +123,8 @@ impl Config {
         for cmd in exit_cmds {
             test.push_back(cmd.clone());
         }
+        test.env_init_len += env.init.len();
+        test.env_exit_len += env.exit.len();
         debug!("add env {} to test case {}", env.name, test.name);
     }
 
@@ -72,6 +136,15 @@ impl Config {
             .clone()
             .into_iter()
             .map(|mut test| {
+                // the start barrier goes after the env init added below
+                let group_sync = self
+                    .concurrences
+                    .iter()
+                    .flatten()
+                    .any(|group| group.syncs(&test.name));
+                if test.sync_start || group_sync {
+                    test.push_front(Cmd::barrier());
+                }
                 for env in &self.envs {
                     if env.tests.contains(&test.name) {
                         Self::set_env(&mut test, &env);
@@ -89,8 +162,174 @@ impl Config {
         tests
     }
 
+    fn name_filter(&self) -> NameFilter {
+        let mut name_filter = self.name_filter.clone();
+        if let Some(ref debug_test) = self.debug_test {
+            info!("Starting debug test: {}", debug_test);
+            match NamePattern::new(&format!("*{}*", debug_test)) {
+                Ok(pattern) => name_filter.add_filter(pattern),
+                Err(e) => warn!("ignore debug test {}: {}", debug_test, e),
+            }
+        }
+        name_filter
+    }
+
+    // apply envs, merge shared inputs and keep the test cases selected by name
+    fn prepare_tests(&self) -> Vec<Test> {
+        let tests = self.apply_envs();
+        let shared_inputs = self.shared_inputs.clone();
+        let name_filter = self.name_filter();
+        if !name_filter.is_empty() {
+            info!("select test cases by {}", name_filter);
+        }
+        let tests = tests
+            .into_iter()
+            .map(|mut test| {
+                test.resolve_refs(&shared_inputs).unwrap();
+                test
+            })
+            .filter_map(|test| test.select(&name_filter))
+            .collect::<Vec<_>>();
+        if tests.is_empty() && !self.tests.is_empty() {
+            warn!("no test case selected by {}!", name_filter);
+        }
+        tests
+    }
+
+    /// Replace the input groups with a data file by one input group for each
+    /// of its rows, `test_cfg` is the path the data paths are relative to.
+    pub fn load_data(&mut self, test_cfg: &str) -> Result<(), String> {
+        let dir = Path::new(test_cfg).parent().unwrap_or(Path::new(""));
+        for test in &mut self.tests {
+            let mut inputs = Vec::new();
+            for input in &test.inputs {
+                let rows = input
+                    .load_data(dir)
+                    .map_err(|e| format!("inputs of test '{}': {}", test.name, e))?;
+                inputs.extend(rows);
+            }
+            test.inputs = inputs;
+        }
+        Ok(())
+    }
+
+    /// Override the args of the input groups and the shared inputs by `vars`,
+    /// the cmds referring to a var missing from the args get it too.
+    pub fn apply_vars(&mut self, vars: Vars) {
+        vars.apply_shared(&mut self.shared_inputs);
+        for test in &mut self.tests {
+            vars.apply(test);
+        }
+        self.vars = vars;
+    }
+
+    /// Print the test cases that would run, without running them.
+    pub fn list(&self) {
+        if let Err(e) = self.validate() {
+            info!("validate config failed: {}", e);
+            return;
+        }
+        let tests = self.prepare_tests();
+        let mut concurrency_tests: Vec<String> = Vec::new();
+        let mut total = 0;
+        if let Some(ref concurrences) = self.concurrences {
+            for concurrency in concurrences {
+                let names = concurrency.list(&tests, &self.tag_filter);
+                if !names.is_empty() {
+                    println!("{} (concurrency group):", concurrency.name());
+                    for name in &names {
+                        println!("    {}", name);
+                    }
+                    total += names.len();
+                }
+                concurrency.record_test(&mut concurrency_tests);
+            }
+        }
+        for test in tests
+            .iter()
+            .filter(|test| !concurrency_tests.contains(&test.name))
+        {
+            for sub_test in test.expand(&self.tag_filter) {
+                println!("{}", sub_test.name);
+                total += 1;
+            }
+        }
+        println!("{} test cases selected.", total);
+    }
+
+    fn settings(&self, max_threads: Option<usize>) -> RunSettings {
+        RunSettings {
+            max_threads,
+            tag_filter: self.tag_filter.clone(),
+            retries: self.retries,
+            max_failures: self.max_failures,
+            seed: self.seed.unwrap_or_else(rand::random),
+            shuffle: self.shuffle,
+            death_timeout: Duration::from_millis(
+                self.death_timeout_ms.unwrap_or(DEFAULT_DEATH_TIMEOUT_MS),
+            ),
+            death_mode: self.death_mode,
+            isolate: self.isolate,
+            limits: self.limits.clone(),
+            executor: self.executor,
+            libs_cfg: self.libs_cfg.clone(),
+            // already checked by validate
+            soak: self.soak.as_deref().and_then(|soak| parse_duration(soak).ok()),
+            soak_interval: self
+                .soak_interval
+                .as_deref()
+                .and_then(|interval| parse_duration(interval).ok())
+                .unwrap_or(DEFAULT_SOAK_INTERVAL),
+            bench_csv: self.bench_csv.clone(),
+            vars: self.vars.clone(),
+        }
+    }
+
+    /// Shrink the failing test case `name`, or a sub test expanded from its
+    /// inputs, and write the minimal test case to `output` or stdout.
+    #[cfg(unix)]
+    pub fn minimize(self, name: &str, output: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        self.validate()?;
+        ResourceEnv::init(
+            self.thread_env.clone(),
+            self.process_env.clone(),
+            self.settings(None),
+        );
+        let test = self
+            .prepare_tests()
+            .iter()
+            .flat_map(|test| test.expand(&self.tag_filter))
+            .find(|test| test.name == name)
+            .ok_or_else(|| format!("test case {} not found, see --list for the names", name))?;
+
+        let minimized = minimize(&test)?;
+        let header = format!(
+            "minimized from test case {} by `hitest minimize`, {} cmds left of {}.\n\
+             it still {}.",
+            name,
+            minimized.test.cmds.len(),
+            test.cmds.len(),
+            minimized.failure
+        );
+        let content = minimized.test.to_case_file(&header)?;
+        info!(
+            "Test case {} minimized to {} cmds after {} runs.",
+            name,
+            minimized.test.cmds.len(),
+            minimized.runs
+        );
+        match output {
+            Some(path) => {
+                std::fs::write(path, content)?;
+                info!("The minimal test case is written to {}.", path);
+            }
+            None => print!("{}", content),
+        }
+        Ok(())
+    }
+
     pub fn run(self, max_threads: Option<usize>) {
-        if self.tests.is_empty() {
+        if self.tests.is_empty() && !self.fuzz_mode {
             info!("no test cases be find, do nothing!");
             return;
         }
@@ -106,71 +345,111 @@ impl Config {
         if let Some(ref thread_env) = self.thread_env {
             thread_env.apply_env_init();
         }
-        ResourceEnv::init(self.thread_env.clone(), self.process_env.clone(), max_threads);
-        // apply envs for test cases
-        let tests = self.apply_envs();
-        // merge shared inputs
-        let shared_inputs = self.shared_inputs.clone();
-        let mut tests = tests
-            .into_iter()
+        let settings = self.settings(max_threads);
+        println!(
+            "HiTest run with seed {}{}, rerun with `--seed {}` to reproduce.",
+            settings.seed,
+            if settings.shuffle { " (shuffled)" } else { "" },
+            settings.seed
+        );
+        if !self.vars.is_empty() {
+            println!("HiTest vars: {}", self.vars);
+        }
+        ResourceEnv::init(
+            self.thread_env.clone(),
+            self.process_env.clone(),
+            settings.clone(),
+        );
+        // apply envs, merge shared inputs and select test cases
+        let mut tests = self.prepare_tests();
+        // the benchmarks of this run append to them, not to the rows of an earlier run
+        let bench_csvs = tests.iter().filter_map(|test| test.bench.as_ref()?.csv.as_ref());
+        for bench_csv in settings.bench_csv.iter().chain(bench_csvs) {
+            if let Err(e) = std::fs::File::create(bench_csv) {
+                warn!("failed to create benchmark csv {}: {}", bench_csv, e);
+            }
+        }
+        let mut concurrences = self.concurrences.clone().unwrap_or_default();
+        if settings.shuffle {
+            tests.shuffle(&mut settings.rng("tests"));
+            concurrences.shuffle(&mut settings.rng("concurrences"));
+        }
+
+        let mut concurrency_tests: Vec<String> = Vec::new();
+        for concurrency in &concurrences {
+            concurrency.record_test(&mut concurrency_tests);
+        }
+
+        // filter out concurrency test cases
+        let remaining_tests = tests
+            .iter()
+            .filter(|test| !concurrency_tests.contains(&test.name))
+            .cloned()
             .map(|mut test| {
-                test.resolve_refs(&shared_inputs).unwrap();
+                if test.serial.is_none() && self.default_serial && test.thread_num == 1 {
+                    warn!(
+                        "Test case {} marked as serial because of default_serial is set",
+                        test.name
+                    );
+                    test.serial = Some(true);
+                }
                 test
             })
             .collect::<Vec<_>>();
 
-        let mut failed_tests = 0;
-        let mut success_tests = 0;
-        tests = if let Some(ref debug_test) = self.debug_test {
-            info!("Starting debug test: {}", debug_test);
-            let tests = tests
-                .iter()
-                .filter(|test| test.name.contains(debug_test))
-                .map(|test| test.clone())
-                .collect::<Vec<_>>();
-            if tests.is_empty() {
-                warn!("Debug test: {} not found!", debug_test);
-            }
-            tests
-        } else {
+        // the result of every concurrency group and test, in run order
+        let run_all = || {
+            let mut results = Vec::new();
+
             // run concurrency group
-            let mut concurrency_tests: Vec<String> = Vec::new();
-            if let Some(ref concurrences) = self.concurrences {
+            if !concurrences.is_empty() {
                 info!("Starting run concurrency groups!");
-                for concurrency in concurrences {
-                    let res = concurrency.run(&tests);
-                    success_tests += res.passed;
-                    failed_tests += res.failed;
-                    concurrency.record_test(&mut concurrency_tests);
+                for concurrency in &concurrences {
+                    let res = if ResourceEnv::should_stop() {
+                        concurrency.not_run(&tests, &self.tag_filter)
+                    } else {
+                        concurrency.run(&tests)
+                    };
+                    results.push(res);
                 }
             }
 
-            // filter out concurrency test cases
-            tests
-                .into_iter()
-                .filter(|test| !concurrency_tests.contains(&test.name))
-                .collect::<Vec<_>>()
+            // run remaining test cases
+            for test in &remaining_tests {
+                results.push(test.run());
+            }
+            results
         };
-
-        tests = tests
-        .into_iter()
-        .map(|mut test| {
-            if test.serial.is_none() && self.default_serial && test.thread_num == 1 {
-                warn!(
-                    "Test case {} marked as serial because of default_serial is set",
-                    test.name
-                );
-                test.serial = Some(true);
+        let mut summary = TestResult::default();
+        if self.fuzz_mode {
+            for fuzz in &self.fuzz {
+                summary.merge(&fuzz.run());
+            }
+        } else {
+            match settings.soak {
+                Some(duration) => {
+                    // every group and test is counted once with its verdict over all iterations
+                    let mut soaked: Vec<TestResult> = Vec::new();
+                    run_soak("all test cases", duration, settings.soak_interval, || {
+                        let results = run_all();
+                        soaked.resize(results.len(), TestResult::default());
+                        let mut iteration = TestResult::default();
+                        for (total, res) in soaked.iter_mut().zip(&results) {
+                            total.merge(res);
+                            iteration.merge(res);
+                        }
+                        iteration
+                    });
+                    for total in &soaked {
+                        summary.merge(&total.aggregate());
+                    }
+                }
+                None => {
+                    for res in run_all() {
+                        summary.merge(&res);
+                    }
+                }
             }
-            test
-        })
-        .collect::<Vec<_>>();
-
-        // run remaining test cases
-        for test in tests {
-            let res = test.run();
-            success_tests += res.passed;
-            failed_tests += res.failed;
         }
 
         // apply env exit
@@ -181,7 +460,7 @@ impl Config {
             process_env.apply_env_exit();
         }
         let mut stdout = StandardStream::stdout(ColorChoice::Always);
-        if failed_tests == 0 {
+        if summary.failed == 0 {
             stdout
                 .set_color(ColorSpec::new().set_fg(Some(Color::Green)))
                 .unwrap();
@@ -193,11 +472,31 @@ impl Config {
         writeln!(
             stdout,
             "Global Summary: Total tests: {}, Success: {}, Failure: {}",
-            failed_tests + success_tests,
-            success_tests,
-            failed_tests
+            summary.total(),
+            summary.passed,
+            summary.failed
         )
         .unwrap();
+        if summary.not_run > 0 {
+            writeln!(
+                stdout,
+                "Stopped early, {} test cases not run: {}",
+                summary.not_run,
+                summary.not_run_tests.join(", ")
+            )
+            .unwrap();
+        }
+        if summary.skipped + summary.disabled + summary.xfail + summary.xpass + summary.flaky > 0 {
+            writeln!(
+                stdout,
+                "Skipped: {}, Disabled: {}, Expected failure: {}, Unexpected pass: {}, Passed after retry: {}",
+                summary.skipped, summary.disabled, summary.xfail, summary.xpass, summary.flaky
+            )
+            .unwrap();
+        }
+        if !self.vars.is_empty() {
+            writeln!(stdout, "Vars: {}", self.vars).unwrap();
+        }
         stdout.reset().unwrap();
         stdout.flush().unwrap();
     }
@@ -0,0 +1,138 @@
+use std::collections::BTreeSet;
+
+// a value of each of some params, as (param, value) sorted by param
+type Tuple = Vec<(usize, usize)>;
+
+// all the ways to choose `t` of `n` params, in lexicographic order
+fn combinations(n: usize, t: usize) -> Vec<Vec<usize>> {
+    fn choose(start: usize, n: usize, t: usize, current: &mut Vec<usize>, out: &mut Vec<Vec<usize>>) {
+        if current.len() == t {
+            out.push(current.clone());
+            return;
+        }
+        for i in start..n {
+            current.push(i);
+            choose(i + 1, n, t, current, out);
+            current.pop();
+        }
+    }
+    let mut out = Vec::new();
+    choose(0, n, t, &mut Vec::new(), &mut out);
+    out
+}
+
+// every value combination of the params
+fn tuples(params: &[usize], sizes: &[usize]) -> Vec<Tuple> {
+    params.iter().fold(vec![Vec::new()], |tuples, &param| {
+        tuples
+            .into_iter()
+            .flat_map(|tuple| {
+                (0..sizes[param]).map(move |value| {
+                    let mut tuple = tuple.clone();
+                    tuple.push((param, value));
+                    tuple
+                })
+            })
+            .collect()
+    })
+}
+
+// the tuple of `params` in a row, None if one of them is not assigned yet
+fn tuple_of(params: &[usize], row: &[Option<usize>]) -> Option<Tuple> {
+    params.iter().map(|&param| row[param].map(|value| (param, value))).collect()
+}
+
+/// Rows of value indexes, one value for each of the params with `sizes`
+/// values, such that every combination of the values of any `strength`
+/// params appears in some row.
+///
+/// Built greedily: every row starts from the first uncovered combination and
+/// takes, param by param, the value covering most of the uncovered ones, the
+/// first value on a tie. No randomness, so the same sizes always give the
+/// same rows.
+pub fn covering_array(sizes: &[usize], strength: usize) -> Vec<Vec<usize>> {
+    if sizes.is_empty() {
+        return vec![Vec::new()];
+    }
+    if sizes.contains(&0) {
+        return Vec::new();
+    }
+    let strength = strength.clamp(1, sizes.len());
+    let combos = combinations(sizes.len(), strength);
+    let mut uncovered: BTreeSet<Tuple> = combos.iter().flat_map(|combo| tuples(combo, sizes)).collect();
+    let mut rows = Vec::new();
+    while let Some(first) = uncovered.iter().next().cloned() {
+        let mut row: Vec<Option<usize>> = vec![None; sizes.len()];
+        for (param, value) in first {
+            row[param] = Some(value);
+        }
+        for param in 0..sizes.len() {
+            if row[param].is_some() {
+                continue;
+            }
+            let gain = |row: &mut Vec<Option<usize>>, value: usize| {
+                row[param] = Some(value);
+                let gain = combos
+                    .iter()
+                    .filter(|combo| combo.contains(&param))
+                    .filter_map(|combo| tuple_of(combo, row))
+                    .filter(|tuple| uncovered.contains(tuple))
+                    .count();
+                row[param] = None;
+                gain
+            };
+            // max_by_key keeps the last maximum, the values are reversed to keep the first
+            let best = (0..sizes[param]).rev().max_by_key(|&value| gain(&mut row, value)).unwrap();
+            row[param] = Some(best);
+        }
+        let row: Vec<usize> = row.into_iter().map(|value| value.unwrap()).collect();
+        for combo in &combos {
+            uncovered.remove(&combo.iter().map(|&param| (param, row[param])).collect::<Tuple>());
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covers(rows: &[Vec<usize>], sizes: &[usize], strength: usize) -> bool {
+        combinations(sizes.len(), strength).iter().all(|combo| {
+            tuples(combo, sizes).iter().all(|tuple| {
+                rows.iter().any(|row| tuple.iter().all(|&(param, value)| row[param] == value))
+            })
+        })
+    }
+
+    #[test]
+    fn test_pairwise() {
+        let sizes = [10; 5];
+        let rows = covering_array(&sizes, 2);
+        assert!(covers(&rows, &sizes, 2));
+        // far fewer than the 100000 of the full product
+        assert!(rows.len() < 200, "{} rows", rows.len());
+        assert_eq!(covering_array(&sizes, 2), rows);
+
+        let sizes = [2, 3, 2, 4];
+        assert!(covers(&covering_array(&sizes, 2), &sizes, 2));
+        let rows = covering_array(&[3, 3, 3, 3], 2);
+        assert!(covers(&rows, &[3, 3, 3, 3], 2));
+        assert!(rows.len() <= 12, "{} rows", rows.len());
+    }
+
+    #[test]
+    fn test_n_wise() {
+        let sizes = [3, 2, 3, 2, 2];
+        let rows = covering_array(&sizes, 3);
+        assert!(covers(&rows, &sizes, 3));
+        assert!(rows.len() < 72);
+
+        // the full product when the strength reaches the number of params
+        assert_eq!(covering_array(&[2, 3], 5).len(), 6);
+        assert_eq!(covering_array(&[4, 2], 1).len(), 4);
+        assert!(covering_array(&[2, 0], 2).is_empty());
+        assert_eq!(covering_array(&[], 2), [Vec::<usize>::new()]);
+    }
+}
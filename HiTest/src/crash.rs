@@ -0,0 +1,211 @@
+use super::Cmd;
+use std::cell::UnsafeCell;
+use std::fmt::{self, Write};
+use std::sync::atomic::{compiler_fence, AtomicUsize, Ordering};
+
+const CONTEXT_SIZE: usize = 1024;
+
+/// What the current thread is running, read by the crash handler.
+///
+/// The handler may run at any point of an update, so the length is cleared
+/// before the bytes are written and set once they are complete.
+struct CrashContext {
+    buf: UnsafeCell<[u8; CONTEXT_SIZE]>,
+    len: AtomicUsize,
+}
+
+thread_local! {
+    // const initialized and nothing to drop, safe to read in a signal handler
+    static CONTEXT: CrashContext = const {
+        CrashContext {
+            buf: UnsafeCell::new([0; CONTEXT_SIZE]),
+            len: AtomicUsize::new(0),
+        }
+    };
+}
+
+// formats into the fixed buffer, truncating what does not fit
+struct BufWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for BufWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// Record the test and cmd about to run on this thread.
+pub fn set_context(test: &str, cmd: &Cmd) {
+    CONTEXT.with(|ctx| {
+        ctx.len.store(0, Ordering::SeqCst);
+        compiler_fence(Ordering::SeqCst);
+        let buf = unsafe { &mut *ctx.buf.get() };
+        let mut writer = BufWriter { buf, len: 0 };
+        let _ = write!(writer, "test: {}, cmd: {}", test, cmd);
+        let len = writer.len;
+        compiler_fence(Ordering::SeqCst);
+        ctx.len.store(len, Ordering::SeqCst);
+    });
+}
+
+/// Clear the context once the thread finished its test.
+pub fn clear_context() {
+    CONTEXT.with(|ctx| ctx.len.store(0, Ordering::SeqCst));
+}
+
+#[cfg(unix)]
+pub use handler::{install_crash_handler, reset_crash_handler};
+
+#[cfg(unix)]
+mod handler {
+    use super::CONTEXT;
+    use log::warn;
+    use nix::libc;
+    use nix::sys::signal::{sigaction, signal, SaFlags, SigAction, SigHandler, SigSet, Signal};
+    use std::sync::atomic::Ordering;
+
+    const CRASH_SIGNALS: [Signal; 3] = [Signal::SIGSEGV, Signal::SIGBUS, Signal::SIGABRT];
+
+    fn write_stderr(bytes: &[u8]) {
+        unsafe {
+            libc::write(libc::STDERR_FILENO, bytes.as_ptr() as *const libc::c_void, bytes.len());
+        }
+    }
+
+    // no allocation in a signal handler, format the number on the stack
+    fn write_number(mut n: u64) {
+        let mut digits = [0u8; 20];
+        let mut i = digits.len();
+        loop {
+            i -= 1;
+            digits[i] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        write_stderr(&digits[i..]);
+    }
+
+    fn signal_name(sig: libc::c_int) -> &'static [u8] {
+        match sig {
+            libc::SIGSEGV => b"SIGSEGV",
+            libc::SIGBUS => b"SIGBUS",
+            libc::SIGABRT => b"SIGABRT",
+            _ => b"signal",
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn thread_id() -> u64 {
+        nix::unistd::gettid().as_raw() as u64
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn thread_id() -> u64 {
+        unsafe { libc::pthread_self() as u64 }
+    }
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    fn write_backtrace() {
+        let mut frames = [std::ptr::null_mut(); 64];
+        unsafe {
+            let n = libc::backtrace(frames.as_mut_ptr(), frames.len() as libc::c_int);
+            libc::backtrace_symbols_fd(frames.as_ptr(), n, libc::STDERR_FILENO);
+        }
+    }
+
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    fn write_backtrace() {
+        write_stderr(b"  (not supported on this platform)\n");
+    }
+
+    extern "C" fn crash_handler(sig: libc::c_int) {
+        write_stderr(b"\nHiTest caught ");
+        write_stderr(signal_name(sig));
+        write_stderr(b" on thread ");
+        write_number(thread_id());
+        write_stderr(b"\n  ");
+        let _ = CONTEXT.try_with(|ctx| {
+            let len = ctx.len.load(Ordering::SeqCst);
+            if len == 0 {
+                write_stderr(b"no test case is running");
+            } else {
+                write_stderr(unsafe { &(&*ctx.buf.get())[..len] });
+            }
+        });
+        write_stderr(b"\nbacktrace:\n");
+        write_backtrace();
+        // the default action is restored by SA_RESETHAND, raise again for
+        // the core dump and the exit status the parent expects
+        unsafe {
+            libc::raise(sig);
+        }
+    }
+
+    /// Print the running test and cmd with a backtrace when the process
+    /// crashes, then re-raise the signal.
+    pub fn install_crash_handler() {
+        // the first backtrace call may load libgcc, not safe in the handler
+        #[cfg(all(target_os = "linux", target_env = "gnu"))]
+        unsafe {
+            let mut frames = [std::ptr::null_mut(); 1];
+            libc::backtrace(frames.as_mut_ptr(), 1);
+        }
+        let action = SigAction::new(
+            SigHandler::Handler(crash_handler),
+            SaFlags::SA_RESETHAND | SaFlags::SA_NODEFER | SaFlags::SA_ONSTACK,
+            SigSet::empty(),
+        );
+        for signal in CRASH_SIGNALS {
+            if let Err(e) = unsafe { sigaction(signal, &action) } {
+                warn!("failed to install crash handler for {}: {}", signal, e);
+            }
+        }
+    }
+
+    /// Restore the default action of the crash signals, in a child expected
+    /// to crash, so its stderr holds only what the test printed.
+    pub fn reset_crash_handler() {
+        for sig in CRASH_SIGNALS {
+            if let Err(e) = unsafe { signal(sig, SigHandler::SigDfl) } {
+                warn!("failed to reset crash handler for {}: {}", sig, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> String {
+        CONTEXT.with(|ctx| {
+            let len = ctx.len.load(Ordering::SeqCst);
+            String::from_utf8_lossy(unsafe { &(&*ctx.buf.get())[..len] }).into_owned()
+        })
+    }
+
+    #[test]
+    fn test_crash_context() {
+        let cmd = Cmd {
+            opfunc: "Call_free".to_string(),
+            args: vec!["mem_idx=1".to_string()],
+            ..Default::default()
+        };
+        set_context("double_free", &cmd);
+        assert_eq!(context(), "test: double_free, cmd: Call_free(mem_idx=1) expect_eq=");
+
+        let long_name = "x".repeat(CONTEXT_SIZE * 2);
+        set_context(&long_name, &cmd);
+        assert_eq!(context().len(), CONTEXT_SIZE);
+
+        clear_context();
+        assert_eq!(context(), "");
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The values of one row of a data file by column, in column order.
+pub type Row = Vec<(String, String)>;
+
+// a cell of a toml or json table, numbers and bools become their text
+fn cell_text<T: std::fmt::Display>(column: &str, value: Option<String>, raw: T) -> Result<String, String> {
+    value.ok_or_else(|| format!("column '{}' should be a string, a number or a bool, got {}", column, raw))
+}
+
+fn read_csv(path: &Path) -> Result<Vec<Row>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|e| e.to_string())?;
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| e.to_string())?;
+            Ok(headers
+                .iter()
+                .zip(record.iter())
+                .map(|(column, value)| (column.to_string(), value.to_string()))
+                .collect())
+        })
+        .collect()
+}
+
+fn read_json(content: &str) -> Result<Vec<Row>, String> {
+    let rows: Vec<serde_json::Map<String, serde_json::Value>> =
+        serde_json::from_str(content).map_err(|e| format!("expect an array of objects, {}", e))?;
+    rows.into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|(column, value)| {
+                    let text = match value {
+                        serde_json::Value::String(ref s) => Some(s.clone()),
+                        serde_json::Value::Number(ref n) => Some(n.to_string()),
+                        serde_json::Value::Bool(b) => Some(b.to_string()),
+                        _ => None,
+                    };
+                    Ok((column.clone(), cell_text(&column, text, &value)?))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn read_toml(content: &str) -> Result<Vec<Row>, String> {
+    let mut file: HashMap<String, Vec<toml::Table>> =
+        toml::from_str(content).map_err(|e| format!("expect [[rows]] tables, {}", e))?;
+    file.remove("rows")
+        .unwrap_or_default()
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|(column, value)| {
+                    let text = match value {
+                        toml::Value::String(ref s) => Some(s.clone()),
+                        toml::Value::Integer(n) => Some(n.to_string()),
+                        toml::Value::Boolean(b) => Some(b.to_string()),
+                        _ => None,
+                    };
+                    Ok((column.clone(), cell_text(&column, text, &value)?))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Read the rows of a .csv file with a header line, a .json array of objects
+/// or a .toml file of `[[rows]]` tables.
+pub fn read_rows(path: &Path) -> Result<Vec<Row>, String> {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    let rows = match ext {
+        "csv" => read_csv(path),
+        "json" | "toml" => {
+            let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+            if ext == "json" {
+                read_json(&content)
+            } else {
+                read_toml(&content)
+            }
+        }
+        _ => Err("expect a .csv, .json or .toml file".to_string()),
+    };
+    rows.map_err(|e| format!("failed to read data {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(name: &str, content: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("hitest_data_{}_{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn row(cells: &[(&str, &str)]) -> Row {
+        cells.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_read_rows() {
+        let csv = write("rw.csv", "name, off, expected\nfirst, 0, 0x111\nsecond, 4, 222\n");
+        let json = write("rw.json", r#"[{"name": "first", "off": 0, "expected": "0x111"}, {"name": "second", "off": 4, "expected": "222"}]"#);
+        let toml = write("rw.toml", "[[rows]]\nname = \"first\"\noff = 0\nexpected = \"0x111\"\n[[rows]]\nname = \"second\"\noff = 4\nexpected = \"222\"\n");
+        for path in [&csv, &json, &toml] {
+            let mut rows = read_rows(path).unwrap();
+            // json and toml tables do not keep the column order
+            for row in &mut rows {
+                row.sort();
+            }
+            assert_eq!(rows.len(), 2, "{}", path.display());
+            assert_eq!(rows[1], row(&[("expected", "222"), ("name", "second"), ("off", "4")]));
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_read_rows_invalid() {
+        let ragged = write("ragged.csv", "a,b\n1,2\n3\n");
+        let nested = write("nested.json", r#"[{"a": [1]}]"#);
+        let object = write("object.json", r#"{"a": 1}"#);
+        for path in [&ragged, &nested, &object] {
+            let err = read_rows(path).unwrap_err();
+            assert!(err.contains(&path.display().to_string()), "{}", err);
+            std::fs::remove_file(path).unwrap();
+        }
+        assert!(read_rows(Path::new("vectors.xlsx")).is_err());
+        assert!(read_rows(Path::new("/nonexistent/rw.csv")).is_err());
+    }
+}
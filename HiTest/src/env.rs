@@ -1,9 +1,14 @@
-use super::Cmd;
+use super::soak::DEFAULT_SOAK_INTERVAL;
+use super::{Cmd, Executor, ResourceLimits, TagFilter, Vars};
 use log::warn;
-use serde::Deserialize;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{RwLock, Once};
+use std::time::Duration;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Env {
     pub name: String,
     pub init: Vec<Cmd>,
@@ -36,13 +41,108 @@ impl Env {
     }
 }
 
+/// Run wide settings shared by all test cases, mostly from the command line.
+#[derive(Debug, Clone)]
+pub struct RunSettings {
+    pub max_threads: Option<usize>,
+    pub tag_filter: TagFilter,
+    pub retries: usize,
+    // stop scheduling new tests after this many failures
+    pub max_failures: Option<usize>,
+    // every randomized decision of the run derives from this seed
+    pub seed: u64,
+    pub shuffle: bool,
+    // how long a death test child may run before it is killed
+    pub death_timeout: Duration,
+    pub death_mode: DeathMode,
+    // run every test in a child process
+    pub isolate: bool,
+    // default limits of death test and isolated children
+    pub limits: ResourceLimits,
+    pub executor: Executor,
+    // library config given to the hitest process started by exec mode
+    pub libs_cfg: String,
+    // repeat the whole run until this much time passed
+    pub soak: Option<Duration>,
+    // how often a soak run prints its report
+    pub soak_interval: Duration,
+    // csv file collecting the results of all benchmarks
+    pub bench_csv: Option<String>,
+    // the -D and --vars values, given to the hitest process started by exec mode too
+    pub vars: Vars,
+}
+
+/// How the child process of a death test is started.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DeathMode {
+    /// Fork the running process, cheap but the child inherits its threads and
+    /// does not run the library constructors again.
+    #[default]
+    Fork,
+    /// Start a fresh hitest process that loads the libraries again and runs
+    /// only this test.
+    Exec,
+}
+
+impl std::str::FromStr for DeathMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fork" => Ok(DeathMode::Fork),
+            "exec" => Ok(DeathMode::Exec),
+            _ => Err(format!("invalid death mode '{}', expect fork or exec", s)),
+        }
+    }
+}
+
+pub const DEFAULT_DEATH_TIMEOUT_MS: u64 = 1000;
+
+impl Default for RunSettings {
+    fn default() -> Self {
+        RunSettings {
+            max_threads: None,
+            tag_filter: TagFilter::default(),
+            retries: 0,
+            max_failures: None,
+            seed: 0,
+            shuffle: false,
+            death_timeout: Duration::from_millis(DEFAULT_DEATH_TIMEOUT_MS),
+            death_mode: DeathMode::Fork,
+            isolate: false,
+            limits: ResourceLimits::default(),
+            executor: Executor::Rayon,
+            libs_cfg: String::new(),
+            soak: None,
+            soak_interval: DEFAULT_SOAK_INTERVAL,
+            bench_csv: None,
+            vars: Vars::default(),
+        }
+    }
+}
+
+impl RunSettings {
+    /// A random generator derived from the run seed and `key`, so the same seed
+    /// gives the same sequence for the same key no matter the execution order.
+    pub fn rng(&self, key: &str) -> StdRng {
+        // FNV-1a, stable across builds unlike the std hasher
+        let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        StdRng::seed_from_u64(self.seed ^ hash)
+    }
+}
+
 pub struct ResourceEnv {
     pub thread_env: Option<Env>,
     pub process_env: Option<Env>,
-    pub max_threads: Option<usize>,
+    pub settings: RunSettings,
 }
 static mut INSTANCE: Option<RwLock<ResourceEnv>> = None;
 static INIT: Once = Once::new();
+static FAILURES: AtomicUsize = AtomicUsize::new(0);
+static STOPPED: AtomicBool = AtomicBool::new(false);
 
 impl ResourceEnv {
     pub fn get_instance() -> Option<&'static RwLock<ResourceEnv>> {
@@ -52,13 +152,66 @@ impl ResourceEnv {
         }
     }
 
-    pub fn init(thread_env: Option<Env>, process_env: Option<Env>, max_threads: Option<usize>) {
+    pub fn init(thread_env: Option<Env>, process_env: Option<Env>, settings: RunSettings) {
         INIT.call_once(|| unsafe {
             INSTANCE = Some(RwLock::new(ResourceEnv {
                 thread_env,
                 process_env,
-                max_threads,
+                settings,
             }));
         });
     }
+
+    /// Count a failed test, and stop the run once max failures is reached.
+    pub fn record_failure() {
+        let failures = FAILURES.fetch_add(1, Ordering::SeqCst) + 1;
+        let max_failures = Self::get_instance()
+            .and_then(|instance| instance.read().unwrap().settings.max_failures);
+        if let Some(max_failures) = max_failures {
+            if failures >= max_failures && !STOPPED.swap(true, Ordering::SeqCst) {
+                warn!(
+                    "{} test cases failed, reached max failures {}, stop running new test cases!",
+                    failures, max_failures
+                );
+            }
+        }
+    }
+
+    /// Whether no new test should be started.
+    pub fn should_stop() -> bool {
+        STOPPED.load(Ordering::SeqCst)
+    }
+
+    /// Run wide settings, default settings when the env is not initialized.
+    pub fn settings() -> RunSettings {
+        Self::get_instance()
+            .map(|instance| instance.read().unwrap().settings.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_rng_derives_from_seed() {
+        let settings = RunSettings {
+            seed: 42,
+            ..Default::default()
+        };
+        let a: Vec<u32> = (0..4).map(|_| settings.rng("test1").gen()).collect();
+        assert!(a.iter().all(|v| *v == a[0]));
+
+        let mut r1 = settings.rng("test1");
+        let mut r2 = settings.rng("test2");
+        assert_ne!(r1.gen::<u64>(), r2.gen::<u64>());
+
+        let other = RunSettings {
+            seed: 43,
+            ..Default::default()
+        };
+        assert_ne!(settings.rng("test1").gen::<u64>(), other.rng("test1").gen::<u64>());
+    }
 }
@@ -0,0 +1,166 @@
+use super::ResourceEnv;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+
+/// Where the parallel threads of thread_num and concurrency groups run.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Executor {
+    /// The rayon pool, sized to the cpu count and reused between tests.
+    #[default]
+    Rayon,
+    /// A new OS thread for every thread of a test, with a fresh TLS page.
+    Threads,
+}
+
+impl std::str::FromStr for Executor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rayon" => Ok(Executor::Rayon),
+            "threads" => Ok(Executor::Threads),
+            _ => Err(format!("invalid executor '{}', expect rayon or threads", s)),
+        }
+    }
+}
+
+// set in a forked worker, the rayon pool threads do not survive fork
+static FORKED: AtomicBool = AtomicBool::new(false);
+
+/// Called in a forked worker process before it runs any test.
+pub fn mark_forked() {
+    FORKED.store(true, Ordering::SeqCst);
+}
+
+/// Run `f` on all `items` in parallel and collect the results in order.
+///
+/// Uses the rayon pool, or one OS thread per item with the threads executor
+/// and in a forked worker.
+pub fn par_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    if !FORKED.load(Ordering::SeqCst) && ResourceEnv::settings().executor == Executor::Rayon {
+        return items.par_iter().map(f).collect();
+    }
+    spawn_map(items, f)
+}
+
+/// Run `f` on all `items`, each on its own OS thread, and collect the results
+/// in order. Needed when the items wait for each other.
+pub fn spawn_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .iter()
+            .map(|item| scope.spawn(move || f(item)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+#[derive(Debug)]
+struct SyncState {
+    parties: usize,
+    waiting: usize,
+    generation: u64,
+}
+
+/// A barrier for the threads of a test, reached by `sync_start` and the
+/// `barrier` pseudo cmd.
+///
+/// Unlike `std::sync::Barrier` a party may leave, so a thread which stops
+/// early, for a failed cmd or a skip, does not block the others forever.
+#[derive(Debug)]
+pub struct SyncPoint {
+    state: Mutex<SyncState>,
+    cvar: Condvar,
+}
+
+impl SyncPoint {
+    pub fn new(parties: usize) -> Self {
+        SyncPoint {
+            state: Mutex::new(SyncState {
+                parties,
+                waiting: 0,
+                generation: 0,
+            }),
+            cvar: Condvar::new(),
+        }
+    }
+
+    fn release(&self, state: &mut SyncState) {
+        state.waiting = 0;
+        state.generation += 1;
+        self.cvar.notify_all();
+    }
+
+    /// Block until all remaining parties are waiting.
+    pub fn wait(&self) {
+        let mut state = self.state.lock().unwrap();
+        let generation = state.generation;
+        state.waiting += 1;
+        if state.waiting >= state.parties {
+            self.release(&mut state);
+            return;
+        }
+        while state.generation == generation {
+            state = self.cvar.wait(state).unwrap();
+        }
+    }
+
+    /// Stop taking part, called once by every party when it finishes.
+    pub fn leave(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.parties = state.parties.saturating_sub(1);
+        if state.waiting > 0 && state.waiting >= state.parties {
+            self.release(&mut state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_executor_from_str() {
+        assert_eq!("rayon".parse(), Ok(Executor::Rayon));
+        assert_eq!("threads".parse(), Ok(Executor::Threads));
+        assert!("pool".parse::<Executor>().is_err());
+        assert_eq!(spawn_map(&[1, 2, 3], |x| x * 2), [2, 4, 6]);
+    }
+
+    #[test]
+    fn test_sync_point_leave() {
+        let sync = SyncPoint::new(4);
+        let passed = AtomicUsize::new(0);
+        let ids: Vec<usize> = (0..4).collect();
+        spawn_map(&ids, |id| {
+            sync.wait();
+            // one party stops early, the others still meet at the next barrier
+            if *id == 0 {
+                sync.leave();
+                return;
+            }
+            sync.wait();
+            passed.fetch_add(1, Ordering::SeqCst);
+            sync.leave();
+        });
+        assert_eq!(passed.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,213 @@
+use regex::Regex;
+
+/// Selects test cases by their tags.
+///
+/// Every `--tag` value is a set of tags joined by `+`, a test matches the set
+/// when it carries all of them. A test is selected when it matches any of the
+/// include sets (or no include set is given) and none of the exclude sets.
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    include: Vec<Vec<String>>,
+    exclude: Vec<Vec<String>>,
+}
+
+fn parse_tag_sets(exprs: &[String]) -> Vec<Vec<String>> {
+    exprs
+        .iter()
+        .map(|expr| {
+            expr.split('+')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .filter(|set| !set.is_empty())
+        .collect()
+}
+
+impl TagFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Self {
+        TagFilter {
+            include: parse_tag_sets(include),
+            exclude: parse_tag_sets(exclude),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, tags: &[String]) -> bool {
+        let has_all = |set: &Vec<String>| set.iter().all(|tag| tags.contains(tag));
+        if !self.include.is_empty() && !self.include.iter().any(has_all) {
+            return false;
+        }
+        !self.exclude.iter().any(has_all)
+    }
+}
+
+/// A test name pattern, a glob by default or a regex when prefixed with `re:`.
+#[derive(Debug, Clone)]
+pub struct NamePattern {
+    source: String,
+    regex: Regex,
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    re
+}
+
+impl NamePattern {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let re = match pattern.strip_prefix("re:") {
+            Some(re) => re.to_string(),
+            None => glob_to_regex(pattern),
+        };
+        let regex =
+            Regex::new(&re).map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+        Ok(NamePattern {
+            source: pattern.to_string(),
+            regex,
+        })
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+impl std::fmt::Display for NamePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Selects test cases by name with `--filter` and `--skip` patterns.
+///
+/// A name is selected when it matches any filter (or no filter is given) and
+/// no skip pattern.
+#[derive(Debug, Clone, Default)]
+pub struct NameFilter {
+    filters: Vec<NamePattern>,
+    skips: Vec<NamePattern>,
+}
+
+impl NameFilter {
+    pub fn new(filters: &[String], skips: &[String]) -> Result<Self, String> {
+        let parse = |patterns: &[String]| -> Result<Vec<NamePattern>, String> {
+            patterns.iter().map(|p| NamePattern::new(p)).collect()
+        };
+        Ok(NameFilter {
+            filters: parse(filters)?,
+            skips: parse(skips)?,
+        })
+    }
+
+    pub fn add_filter(&mut self, pattern: NamePattern) {
+        self.filters.push(pattern);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty() && self.skips.is_empty()
+    }
+
+    /// Whether any of `names` matches a filter pattern.
+    pub fn is_filtered(&self, names: &[&str]) -> bool {
+        self.filters.is_empty()
+            || self
+                .filters
+                .iter()
+                .any(|p| names.iter().any(|name| p.is_match(name)))
+    }
+
+    /// Whether any of `names` matches a skip pattern.
+    pub fn is_skipped(&self, names: &[&str]) -> bool {
+        self.skips
+            .iter()
+            .any(|p| names.iter().any(|name| p.is_match(name)))
+    }
+
+    pub fn matches(&self, names: &[&str]) -> bool {
+        self.is_filtered(names) && !self.is_skipped(names)
+    }
+}
+
+impl std::fmt::Display for NameFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |patterns: &Vec<NamePattern>| {
+            patterns
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        write!(f, "filter: [{}], skip: [{}]", join(&self.filters), join(&self.skips))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_empty_filter_matches_all() {
+        let filter = TagFilter::new(&[], &[]);
+        assert!(filter.is_empty());
+        assert!(filter.matches(&[]));
+        assert!(filter.matches(&tags(&["smoke"])));
+    }
+
+    #[test]
+    fn test_include_any_of_sets() {
+        let filter = TagFilter::new(&tags(&["smoke", "dma+slow"]), &[]);
+        assert!(filter.matches(&tags(&["smoke"])));
+        assert!(filter.matches(&tags(&["dma", "slow"])));
+        assert!(!filter.matches(&tags(&["dma"])));
+        assert!(!filter.matches(&[]));
+    }
+
+    #[test]
+    fn test_exclude_wins() {
+        let filter = TagFilter::new(&tags(&["smoke"]), &tags(&["dma"]));
+        assert!(filter.matches(&tags(&["smoke"])));
+        assert!(!filter.matches(&tags(&["smoke", "dma"])));
+        let filter = TagFilter::new(&[], &tags(&["dma+slow"]));
+        assert!(filter.matches(&tags(&["dma"])));
+        assert!(!filter.matches(&tags(&["slow", "dma"])));
+    }
+
+    #[test]
+    fn test_name_pattern_glob_and_regex() {
+        let glob = NamePattern::new("test_rw_*").unwrap();
+        assert!(glob.is_match("test_rw_u32"));
+        assert!(glob.is_match("test_rw_u32_ipt4_off=0x100"));
+        assert!(!glob.is_match("group1_test_rw_u32"));
+
+        let re = NamePattern::new("re:^test_rw_u(32|64)$").unwrap();
+        assert!(re.is_match("test_rw_u64"));
+        assert!(!re.is_match("test_rw_u64_ipt4"));
+
+        assert!(NamePattern::new("re:(").is_err());
+    }
+
+    #[test]
+    fn test_name_filter_skip() {
+        let filter = NameFilter::new(&tags(&["test_rw_*"]), &tags(&["*u64*"])).unwrap();
+        assert!(filter.matches(&["test_rw_u32"]));
+        assert!(!filter.matches(&["test_rw_u64"]));
+        assert!(!filter.matches(&["test_rw_u32", "test_rw_u32_val=u64"]));
+        assert!(!filter.matches(&["Test_str_fill"]));
+    }
+}
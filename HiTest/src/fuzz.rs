@@ -0,0 +1,306 @@
+use super::cmd::TEST_RET_SKIP;
+use super::{Cmd, Condition, ResourceEnv, Test, TestResult};
+use log::{error, info, warn};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// tried for the params without a domain
+const INTERESTING_VALUES: [i64; 12] = [
+    0,
+    1,
+    -1,
+    2,
+    255,
+    256,
+    511,
+    512,
+    4096,
+    i32::MAX as i64,
+    i64::MAX,
+    i64::MIN,
+];
+
+/// Values a fuzzed parameter is drawn from.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamDomain {
+    /// Any number between the two, both included.
+    Range([i64; 2]),
+    /// One of these numbers, such as the param_page indexes in use.
+    Pool(Vec<i64>),
+    /// One of these strings.
+    Dict(Vec<String>),
+}
+
+impl ParamDomain {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            ParamDomain::Range([min, max]) if min > max => {
+                Err(format!("range [{}, {}] is empty", min, max))
+            }
+            ParamDomain::Pool(values) if values.is_empty() => Err("pool is empty".to_string()),
+            ParamDomain::Dict(words) if words.is_empty() => Err("dict is empty".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> String {
+        match self {
+            ParamDomain::Range([min, max]) => rng.gen_range(*min..=*max).to_string(),
+            ParamDomain::Pool(values) => values.choose(rng).unwrap().to_string(),
+            ParamDomain::Dict(words) => format!("'{}'", words.choose(rng).unwrap()),
+        }
+    }
+}
+
+fn default_iterations() -> usize {
+    100
+}
+
+fn default_max_cmds() -> usize {
+    10
+}
+
+fn default_out_dir() -> String {
+    String::from("fuzz_findings")
+}
+
+/// Random cmd sequences of the chosen opfuncs, each run in a child process.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FuzzCampaign {
+    pub name: String,
+    pub opfuncs: Vec<String>,
+    // domain of a param by its name, or by "opfunc.param" for one opfunc only
+    #[serde(default)]
+    pub params: HashMap<String, ParamDomain>,
+    // the return values to check, other cmds may return anything
+    #[serde(default)]
+    pub expect: HashMap<String, Condition>,
+    // number of sequences to run
+    #[serde(default = "default_iterations")]
+    pub iterations: usize,
+    #[serde(default = "default_max_cmds")]
+    pub max_cmds: usize,
+    // where the failing sequences are saved as test cases
+    #[serde(default = "default_out_dir")]
+    pub out_dir: String,
+}
+
+impl FuzzCampaign {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.opfuncs.is_empty() {
+            return Err(format!("fuzz campaign '{}' has no opfuncs", self.name));
+        }
+        if self.max_cmds == 0 {
+            return Err(format!("max_cmds of fuzz campaign '{}' should be positive", self.name));
+        }
+        for (param, domain) in &self.params {
+            domain
+                .validate()
+                .map_err(|e| format!("param '{}' of fuzz campaign '{}': {}", param, self.name, e))?;
+        }
+        Ok(())
+    }
+
+    fn sample_param(&self, rng: &mut StdRng, opfunc: &str, param: &str) -> String {
+        let domain = self
+            .params
+            .get(&format!("{}.{}", opfunc, param))
+            .or_else(|| self.params.get(param));
+        match domain {
+            Some(domain) => domain.sample(rng),
+            None => INTERESTING_VALUES.choose(rng).unwrap().to_string(),
+        }
+    }
+
+    /// The test case of one iteration, the same for the same seed.
+    fn sequence(&self, iteration: usize, params: &HashMap<String, Vec<String>>) -> Test {
+        let name = format!("{}_{}", self.name, iteration);
+        let mut rng = ResourceEnv::settings().rng(&name);
+        let len = rng.gen_range(1..=self.max_cmds);
+        let cmds = (0..len)
+            .map(|_| {
+                let opfunc = self.opfuncs.choose(&mut rng).unwrap();
+                let args = params[opfunc]
+                    .iter()
+                    .map(|param| format!("{}={}", param, self.sample_param(&mut rng, opfunc, param)))
+                    .collect();
+                // the skip code is handled before the check, so this accepts any value
+                let condition = self
+                    .expect
+                    .get(opfunc)
+                    .cloned()
+                    .unwrap_or_else(|| Condition::Ne(TEST_RET_SKIP.to_string()));
+                Cmd {
+                    opfunc: opfunc.clone(),
+                    condition,
+                    args,
+                    perf: false,
+                }
+            })
+            .collect();
+        Test {
+            name,
+            cmds,
+            thread_num: 1,
+            break_if_fail: true,
+            ..Default::default()
+        }
+    }
+
+    // params of every opfunc from the lib config
+    fn opfunc_params(&self) -> Result<HashMap<String, Vec<String>>, Box<dyn std::error::Error>> {
+        let lib_parser = libparser::LibParse::get_instance()?.read().unwrap();
+        let mut params = HashMap::new();
+        for opfunc in &self.opfuncs {
+            let paras = lib_parser
+                .params(opfunc)
+                .map_err(|e| format!("fuzz campaign '{}': {}", self.name, e))?;
+            params.insert(opfunc.clone(), paras);
+        }
+        Ok(params)
+    }
+
+    fn save(&self, test: &Test, header: &str) -> Result<String, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&self.out_dir)?;
+        let path = format!("{}/{}.toml", self.out_dir, test.name);
+        std::fs::write(&path, test.to_case_file(header)?)?;
+        Ok(path)
+    }
+
+    /// Run the sequences one after another, each in a child process, and
+    /// save those which fail, crash or hang.
+    #[cfg(unix)]
+    pub fn run(&self) -> TestResult {
+        let params = match self.opfunc_params() {
+            Ok(params) => params,
+            Err(e) => {
+                error!("{}", e);
+                return TestResult {
+                    failed: 1,
+                    ..Default::default()
+                };
+            }
+        };
+        info!("Fuzz {} with {} sequences of {:?}.", self.name, self.iterations, self.opfuncs);
+        let seed = ResourceEnv::settings().seed;
+        let mut result = TestResult::default();
+        for iteration in 0..self.iterations {
+            if ResourceEnv::should_stop() {
+                result.add_not_run(self.name.clone(), self.iterations - iteration);
+                break;
+            }
+            let test = self.sequence(iteration, &params);
+            let verdict = match test.probe() {
+                Ok((verdict, _)) => verdict,
+                Err(e) => {
+                    error!("Failed to run fuzz sequence {}: {}", test.name, e);
+                    result.failed += 1;
+                    ResourceEnv::record_failure();
+                    continue;
+                }
+            };
+            if !verdict.is_failure() {
+                if verdict == super::ChildVerdict::Passed {
+                    result.passed += 1;
+                } else {
+                    result.skipped += 1;
+                }
+                continue;
+            }
+            result.failed += 1;
+            ResourceEnv::record_failure();
+            let header = format!(
+                "found by fuzz campaign {}, the sequence {}.\n\
+                 generated again by `--fuzz --seed {}`, expect_ne = {} accepts any return value.",
+                self.name, verdict, seed, TEST_RET_SKIP
+            );
+            match self.save(&test, &header) {
+                Ok(path) => warn!("Fuzz sequence {} {}, saved to {}.", test.name, verdict, path),
+                Err(e) => error!("Fuzz sequence {} {}, failed to save it: {}", test.name, verdict, e),
+            }
+        }
+        if result.failed > 0 {
+            error!("Fuzz {} finished, {} failing sequences saved to {}! {}\n", self.name, result.failed, self.out_dir, result);
+        } else {
+            info!("Fuzz {} finished! {}\n", self.name, result);
+        }
+        result
+    }
+
+    #[cfg(not(unix))]
+    pub fn run(&self) -> TestResult {
+        error!("fuzz is not supported on this platform.");
+        TestResult {
+            failed: 1,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn campaign() -> FuzzCampaign {
+        toml::from_str(
+            r#"
+            name = "mem"
+            opfuncs = ["Call_malloc", "Call_free"]
+            max_cmds = 5
+            expect = { Call_free = { expect_eq = 0 } }
+            [params]
+            len = { range = [1, 64] }
+            mem_idx = { pool = [1, 2] }
+            "Call_free.mem_idx" = { pool = [3] }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fuzz_sequence() {
+        let fuzz = campaign();
+        assert!(fuzz.validate().is_ok());
+        let params = HashMap::from([
+            ("Call_malloc".to_string(), vec!["len".to_string(), "mem_idx".to_string()]),
+            ("Call_free".to_string(), vec!["mem_idx".to_string()]),
+        ]);
+        let test = fuzz.sequence(7, &params);
+        assert_eq!(test.name, "mem_7");
+        assert!((1..=5).contains(&test.cmds.len()));
+        for cmd in &test.cmds {
+            if cmd.opfunc == "Call_free" {
+                assert_eq!(cmd.args, ["mem_idx=3"]);
+                assert_eq!(cmd.condition, Condition::Eq("0".to_string()));
+            } else {
+                let len: i64 = cmd.args[0].strip_prefix("len=").unwrap().parse().unwrap();
+                assert!((1..=64).contains(&len));
+                assert!(cmd.args[1] == "mem_idx=1" || cmd.args[1] == "mem_idx=2");
+                assert_eq!(cmd.condition, Condition::Ne(TEST_RET_SKIP.to_string()));
+            }
+        }
+        // the same seed gives the same sequence
+        assert_eq!(fuzz.sequence(7, &params).to_string(), test.to_string());
+
+        // a saved sequence loads back as a test case
+        let file: HashMap<String, Vec<Test>> = toml::from_str(&test.to_case_file("a\nb").unwrap()).unwrap();
+        assert_eq!(file["tests"][0].to_string(), test.to_string());
+    }
+
+    #[test]
+    fn test_fuzz_domains() {
+        let mut rng = ResourceEnv::settings().rng("domains");
+        assert_eq!(ParamDomain::Dict(vec!["ab".to_string()]).sample(&mut rng), "'ab'");
+        assert_eq!(ParamDomain::Range([5, 5]).sample(&mut rng), "5");
+        assert!(ParamDomain::Range([5, 4]).validate().is_err());
+        assert!(ParamDomain::Pool(vec![]).validate().is_err());
+
+        let mut fuzz = campaign();
+        fuzz.params.insert("len".to_string(), ParamDomain::Dict(vec![]));
+        assert!(fuzz.validate().is_err());
+    }
+}
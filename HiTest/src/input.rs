@@ -1,5 +1,9 @@
+use rand::{Rng, SeedableRng};
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use super::data::read_rows;
+use super::is_var_name;
 
 fn default_input_name() -> String {
     use std::sync::atomic::{AtomicUsize, Ordering};
@@ -19,6 +23,177 @@ pub struct InputGroup {
     pub break_if_fail: Option<bool>,
     #[serde(default)]
     pub refs: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub expect_signal: Option<String>,
+    #[serde(default)]
+    pub expect_exit_code: Option<i32>,
+    #[serde(default)]
+    pub expand: Expand,
+    // keys whose lists are paired by position instead of combined
+    #[serde(default)]
+    pub zip: Vec<Vec<String>>,
+    // a csv, json or toml table, one input group for every row
+    #[serde(default)]
+    pub data: Option<String>,
+}
+
+// the column naming the input group of a row
+const DATA_NAME_COLUMN: &str = "name";
+
+/// The values of some args that go together in a sub test, as (key, value).
+pub type Assignment = Vec<(String, String)>;
+
+impl InputGroup {
+    /// One input group for every row of the data file, the columns are args
+    /// and the name column, or the row number from 1, is added to the name.
+    /// A relative path starts from `dir`.
+    pub fn load_data(&self, dir: &Path) -> Result<Vec<InputGroup>, String> {
+        let Some(ref data) = self.data else {
+            return Ok(vec![self.clone()]);
+        };
+        let rows = read_rows(&dir.join(data))?;
+        rows.into_iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let mut input = self.clone();
+                input.data = None;
+                input.name = format!("{}_{}", self.name, i + 1);
+                for (column, value) in row {
+                    if column == DATA_NAME_COLUMN {
+                        if !value.is_empty() {
+                            input.name = format!("{}_{}", self.name, value);
+                        }
+                    } else if input.args.insert(column.clone(), ArgValue::Single(value)).is_some() {
+                        return Err(format!("column '{}' of {} is an arg of input group '{}' too", column, data, self.name));
+                    }
+                }
+                Ok(input)
+            })
+            .collect()
+    }
+
+    // with expand = "zip" all the List and Range args are zipped together
+    fn zip_groups(&self) -> Vec<Vec<String>> {
+        if self.expand != Expand::Zip {
+            return self.zip.clone();
+        }
+        let mut keys: Vec<String> = self
+            .args
+            .iter()
+            .filter(|(_, value)| !matches!(value, ArgValue::Single(_)))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+        vec![keys]
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.expand == Expand::Zip && !self.zip.is_empty() {
+            return Err(format!("input group '{}' zips all its lists, zip is not needed", self.name));
+        }
+        for (key, value) in &self.args {
+            if !is_var_name(key) {
+                return Err(format!(
+                    "arg '{}' of input group '{}' is not a var name, expect letters, digits and '_' only",
+                    key, self.name
+                ));
+            }
+            if let ArgValue::Range(range) = value {
+                range
+                    .validate()
+                    .map_err(|e| format!("range '{}' of input group '{}': {}", key, self.name, e))?;
+            }
+        }
+        let mut zipped: Vec<&String> = Vec::new();
+        for group in &self.zip_groups() {
+            let mut lens = Vec::new();
+            for key in group {
+                if zipped.contains(&key) {
+                    return Err(format!("'{}' of input group '{}' is zipped twice", key, self.name));
+                }
+                zipped.push(key);
+                match self.args.get(key) {
+                    Some(ArgValue::Single(_)) | None => {
+                        return Err(format!("zipped '{}' is not a list arg of input group '{}'", key, self.name))
+                    }
+                    Some(value) => lens.push(format!("{} has {}", key, value.values().len())),
+                }
+            }
+            let len = |key: &String| self.args[key].values().len();
+            if group.iter().any(|key| len(key) != len(&group[0])) {
+                return Err(format!("zipped lists of input group '{}' differ in length: {}", self.name, lens.join(", ")));
+            }
+        }
+        Ok(())
+    }
+
+    /// The List and Range args as the dimensions of the expansion, sorted by
+    /// key: every value of an arg, or every position of a zip group, is one
+    /// assignment of its dimension.
+    pub fn dims(&self) -> Vec<Vec<Assignment>> {
+        let groups = self.zip_groups();
+        // by the first key, a list may be empty
+        let mut dims: Vec<(&String, Vec<Assignment>)> = groups
+            .iter()
+            .filter(|group| !group.is_empty())
+            .map(|group| {
+                let values: Vec<Vec<String>> = group.iter().map(|key| self.args[key].values()).collect();
+                let dim = (0..values[0].len())
+                    .map(|i| group.iter().cloned().zip(values.iter().map(|v| v[i].clone())).collect())
+                    .collect();
+                (&group[0], dim)
+            })
+            .collect();
+        for (key, value) in &self.args {
+            if matches!(value, ArgValue::Single(_)) || groups.iter().any(|group| group.contains(key)) {
+                continue;
+            }
+            dims.push((key, value.values().into_iter().map(|v| vec![(key.clone(), v)]).collect()));
+        }
+        dims.sort_by(|a, b| a.0.cmp(b.0));
+        dims.into_iter().map(|(_, dim)| dim).collect()
+    }
+}
+
+/// How the List and Range args of an input group are combined into sub tests.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(try_from = "String")]
+pub enum Expand {
+    /// Every combination of all the args, "product".
+    #[default]
+    Product,
+    /// Every combination of the values of any n args, "pairwise" for 2 or
+    /// "n-wise" such as "3-wise".
+    NWise(usize),
+    /// The values of all the args paired by position, "zip".
+    Zip,
+}
+
+impl std::str::FromStr for Expand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid expand '{}', expect product, pairwise, n-wise such as 3-wise or zip", s);
+        match s {
+            "product" => Ok(Expand::Product),
+            "pairwise" => Ok(Expand::NWise(2)),
+            "zip" => Ok(Expand::Zip),
+            _ => match s.strip_suffix("-wise").map(str::parse) {
+                Some(Ok(n)) if n > 0 => Ok(Expand::NWise(n)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Expand {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -29,9 +204,344 @@ pub enum ArgValue {
     Range(RangeExpr),
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+/// A number of a range, from i64::MIN to u64::MAX. An integer, or a string
+/// such as "0x1000" or "18446744073709551615" for the values above i64::MAX.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(try_from = "RangeNumRepr")]
+pub struct RangeNum {
+    pub value: i128,
+    // the values of a range starting from a hex number are hex too
+    pub hex: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RangeNumRepr {
+    Int(i64),
+    Str(String),
+}
+
+impl From<i64> for RangeNum {
+    fn from(value: i64) -> Self {
+        RangeNum {
+            value: value as i128,
+            hex: false,
+        }
+    }
+}
+
+impl std::str::FromStr for RangeNum {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid range number '{}', expect a number from i64::MIN to u64::MAX", s);
+        let num = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => RangeNum {
+                value: u64::from_str_radix(hex, 16).map_err(|_| invalid())? as i128,
+                hex: true,
+            },
+            None => RangeNum {
+                value: s.parse().map_err(|_| invalid())?,
+                hex: false,
+            },
+        };
+        if num.value < i64::MIN as i128 || num.value > u64::MAX as i128 {
+            return Err(invalid());
+        }
+        Ok(num)
+    }
+}
+
+impl TryFrom<RangeNumRepr> for RangeNum {
+    type Error = String;
+
+    fn try_from(repr: RangeNumRepr) -> Result<Self, Self::Error> {
+        match repr {
+            RangeNumRepr::Int(value) => Ok(value.into()),
+            RangeNumRepr::Str(s) => s.parse(),
+        }
+    }
+}
+
+/// Sample some values of a range instead of all of them.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct RangeRandom {
+    pub count: usize,
+    // the same seed picks the same values, so the sub test names are stable
+    #[serde(default)]
+    pub seed: u64,
+}
+
+// the most values a range lists, a longer one should sample with random
+const MAX_RANGE_LEN: u128 = 1 << 16;
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
 pub struct RangeExpr {
-    pub start: i32,
-    pub end: i32,
-    pub step: Option<i32>,
+    pub start: RangeNum,
+    pub end: RangeNum,
+    // added to every value, counting down when start > end, default is 1
+    pub step: Option<RangeNum>,
+    #[serde(default)]
+    pub end_exclusive: bool,
+    // multiplies every value instead, such as 2 for the powers of two
+    pub mul: Option<RangeNum>,
+    pub random: Option<RangeRandom>,
+}
+
+impl RangeExpr {
+    pub fn validate(&self) -> Result<(), String> {
+        let (start, end) = (self.start.value, self.end.value);
+        if matches!(self.step, Some(step) if step.value <= 0) {
+            return Err("step should be positive, a range with start > end counts down".to_string());
+        }
+        if let Some(mul) = self.mul {
+            if self.step.is_some() {
+                return Err("step and mul are mutually exclusive".to_string());
+            }
+            if mul.value < 2 {
+                return Err("mul should be at least 2".to_string());
+            }
+            if start <= 0 || start > end {
+                return Err("a range with mul should have 0 < start <= end".to_string());
+            }
+        }
+        match self.random {
+            Some(random) if random.count == 0 => return Err("random count should be positive".to_string()),
+            Some(random) if random.count as u128 > MAX_RANGE_LEN => {
+                return Err(format!("random count {} is more than {}", random.count, MAX_RANGE_LEN))
+            }
+            Some(_) => {}
+            None if self.len() > MAX_RANGE_LEN => {
+                return Err(format!(
+                    "range has {} values, more than {}, sample some of them with random",
+                    self.len(),
+                    MAX_RANGE_LEN
+                ))
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    // the values of a geometric range, at most 64 of them
+    fn powers(&self, mul: i128) -> Vec<i128> {
+        let mut powers = Vec::new();
+        let mut value = Some(self.start.value);
+        while let Some(v) = value.filter(|&v| v < self.end.value || (v == self.end.value && !self.end_exclusive)) {
+            powers.push(v);
+            value = v.checked_mul(mul);
+        }
+        powers
+    }
+
+    // the number of values, an arithmetic range may be too long to list
+    fn len(&self) -> u128 {
+        if let Some(mul) = self.mul {
+            return self.powers(mul.value).len() as u128;
+        }
+        let step = self.step.map_or(1, |step| step.value) as u128;
+        let span = self.start.value.abs_diff(self.end.value);
+        if self.end_exclusive && span.is_multiple_of(step) {
+            span / step
+        } else {
+            span / step + 1
+        }
+    }
+
+    fn nth(&self, i: u128) -> i128 {
+        if let Some(mul) = self.mul {
+            return self.powers(mul.value)[i as usize];
+        }
+        let offset = (i * self.step.map_or(1, |step| step.value) as u128) as i128;
+        if self.start.value <= self.end.value {
+            self.start.value + offset
+        } else {
+            self.start.value - offset
+        }
+    }
+
+    fn format(&self, value: i128) -> String {
+        if self.start.hex && value >= 0 {
+            format!("{:#x}", value)
+        } else {
+            value.to_string()
+        }
+    }
+
+    /// All the values, or `random.count` of them in order, empty for an
+    /// invalid range.
+    pub fn values(&self) -> Vec<String> {
+        if self.validate().is_err() {
+            return Vec::new();
+        }
+        let len = self.len();
+        let indexes: Box<dyn Iterator<Item = u128>> = match self.random {
+            Some(random) if (random.count as u128) < len => {
+                let mut rng = rand::rngs::StdRng::seed_from_u64(random.seed);
+                let mut picked = BTreeSet::new();
+                while picked.len() < random.count {
+                    picked.insert(rng.gen_range(0..len));
+                }
+                Box::new(picked.into_iter())
+            }
+            _ => Box::new(0..len),
+        };
+        indexes.map(|i| self.format(self.nth(i))).collect()
+    }
+}
+
+impl ArgValue {
+    /// The values a sub test may take, one for a Single.
+    pub fn values(&self) -> Vec<String> {
+        match self {
+            ArgValue::Single(value) => vec![value.clone()],
+            ArgValue::List(items) => items.clone(),
+            ArgValue::Range(range) => range.values(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_from_str() {
+        assert_eq!("product".parse(), Ok(Expand::Product));
+        assert_eq!("pairwise".parse(), Ok(Expand::NWise(2)));
+        assert_eq!("3-wise".parse(), Ok(Expand::NWise(3)));
+        for invalid in ["0-wise", "x-wise", "triple"] {
+            assert!(invalid.parse::<Expand>().is_err(), "{}", invalid);
+        }
+        let input: InputGroup = toml::from_str("expand = \"pairwise\"").unwrap();
+        assert_eq!(input.expand, Expand::NWise(2));
+        assert!(toml::from_str::<InputGroup>("expand = \"all\"").is_err());
+    }
+
+    fn range(s: &str) -> RangeExpr {
+        toml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_range_values() {
+        assert_eq!(range("start = 0\nend = 10\nstep = 5").values(), ["0", "5", "10"]);
+        assert_eq!(range("start = 0\nend = 10\nstep = 5\nend_exclusive = true").values(), ["0", "5"]);
+        assert_eq!(range("start = 3\nend = 1").values(), ["3", "2", "1"]);
+        assert_eq!(range("start = -1\nend = -7\nstep = 3").values(), ["-1", "-4", "-7"]);
+        assert_eq!(range("start = 1\nend = 1\nend_exclusive = true").values(), Vec::<String>::new());
+        assert_eq!(
+            range("start = \"0xfffffffffffffffe\"\nend = \"18446744073709551615\"").values(),
+            ["0xfffffffffffffffe", "0xffffffffffffffff"]
+        );
+        let powers = range("start = 1\nend = 1048576\nmul = 2").values();
+        assert_eq!(powers.len(), 21);
+        assert_eq!((powers[1].as_str(), powers[20].as_str()), ("2", "1048576"));
+        assert_eq!(range("start = \"0x1000\"\nend = \"0x10000\"\nmul = 4\nend_exclusive = true").values(), ["0x1000", "0x4000"]);
+        // the next power does not fit in i128
+        let max = "start = \"0xffffffffffffffff\"\nend = \"0xffffffffffffffff\"\nmul = \"0xffffffffffffffff\"";
+        assert_eq!(range(max).values(), ["0xffffffffffffffff"]);
+    }
+
+    #[test]
+    fn test_range_random() {
+        let sampled = range("start = 0\nend = \"0xffffffffffffffff\"\nstep = 4096\nrandom = { count = 5, seed = 7 }").values();
+        assert_eq!(sampled.len(), 5);
+        for value in &sampled {
+            assert_eq!(value.parse::<u64>().unwrap() % 4096, 0);
+        }
+        let again = range("start = 0\nend = \"0xffffffffffffffff\"\nstep = 4096\nrandom = { count = 5, seed = 7 }").values();
+        assert_eq!(sampled, again);
+        // all of them when the range is not longer than count
+        assert_eq!(range("start = 1\nend = 3\nrandom = { count = 5 }").values(), ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn test_range_validate() {
+        for invalid in [
+            "start = 0\nend = 10\nstep = 0",
+            "start = 0\nend = 10\nstep = -1",
+            "start = 1\nend = 10\nmul = 1",
+            "start = 0\nend = 10\nmul = 2",
+            "start = 1\nend = 10\nmul = 2\nstep = 1",
+            "start = 1\nend = 10\nrandom = { count = 0 }",
+            "start = 1\nend = 10\nrandom = { count = 1000000 }",
+            "start = 0\nend = \"0xffffffffffffffff\"",
+        ] {
+            assert!(range(invalid).validate().is_err(), "{}", invalid);
+            assert!(range(invalid).values().is_empty(), "{}", invalid);
+        }
+        assert!(toml::from_str::<RangeExpr>("start = \"0x1ffffffffffffffff\"\nend = 1").is_err());
+        assert!(toml::from_str::<RangeExpr>("start = \"-9223372036854775809\"\nend = 1").is_err());
+        let long = range("start = 0\nend = 65536").validate().unwrap_err();
+        assert_eq!(long, "range has 65537 values, more than 65536, sample some of them with random");
+        assert_eq!(range("start = 1\nend = 65536").values().len(), 65536);
+        let input = zip_input("args = { a = { start = 0, end = 1, step = 0 } }");
+        assert!(input.validate().unwrap_err().contains("range 'a'"));
+        let input = zip_input("args = { mem-idx = [\"1\", \"2\"] }");
+        assert!(input.validate().unwrap_err().contains("arg 'mem-idx' of input group 'z' is not a var name"));
+    }
+
+    #[test]
+    fn test_load_data() {
+        let dir = std::env::temp_dir().join(format!("hitest_input_data_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("vectors")).unwrap();
+        std::fs::write(dir.join("vectors/rw.csv"), "name,off,expected\nlow,0,111\n,4,$val\n").unwrap();
+
+        let input: InputGroup = toml::from_str("name = \"v\"\ndata = \"vectors/rw.csv\"\nargs = { len = [\"1\", \"2\"] }").unwrap();
+        let inputs = input.load_data(&dir).unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(inputs[0].name, "v_low");
+        assert_eq!(inputs[1].name, "v_2");
+        assert_eq!(inputs[1].args["off"], ArgValue::Single("4".to_string()));
+        assert_eq!(inputs[1].args["expected"], ArgValue::Single("$val".to_string()));
+        assert_eq!(inputs[1].args["len"], ArgValue::List(vec!["1".to_string(), "2".to_string()]));
+        assert!(inputs[1].data.is_none());
+
+        let input: InputGroup = toml::from_str("name = \"v\"\ndata = \"vectors/rw.csv\"\nargs = { off = \"8\" }").unwrap();
+        assert!(input.load_data(&dir).unwrap_err().contains("column 'off'"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn zip_input(s: &str) -> InputGroup {
+        toml::from_str(&format!("name = \"z\"\n{}", s)).unwrap()
+    }
+
+    #[test]
+    fn test_zip_dims() {
+        let input = zip_input(r#"zip = [["off", "expected"]]
+            args = { off = ["0", "4", "8"], expected = ["111", "222", "333"], len = ["1", "2"], s = "x" }"#);
+        assert!(input.validate().is_ok());
+        let dims = input.dims();
+        assert_eq!(dims.len(), 2);
+        // sorted by the first key of a dimension
+        assert_eq!(dims[0], [vec![("len".to_string(), "1".to_string())], vec![("len".to_string(), "2".to_string())]]);
+        assert_eq!(dims[1][1], [("off".to_string(), "4".to_string()), ("expected".to_string(), "222".to_string())]);
+
+        let input = zip_input(r#"expand = "zip"
+            args = { b = ["1", "2"], a = { start = 1, end = 2 } }"#);
+        assert!(input.validate().is_ok());
+        assert_eq!(input.dims()[0][1], [("a".to_string(), "2".to_string()), ("b".to_string(), "2".to_string())]);
+    }
+
+    #[test]
+    fn test_zip_validate() {
+        for invalid in [
+            r#"zip = [["a", "b"]]
+               args = { a = ["1", "2"], b = ["1"] }"#,
+            r#"zip = [["a", "c"]]
+               args = { a = ["1"], b = ["1"] }"#,
+            r#"zip = [["a", "b"]]
+               args = { a = ["1"], b = "1" }"#,
+            r#"zip = [["a", "b"], ["b"]]
+               args = { a = ["1"], b = ["1"] }"#,
+            r#"expand = "zip"
+               zip = [["a", "b"]]
+               args = { a = ["1"], b = ["1"] }"#,
+        ] {
+            assert!(zip_input(invalid).validate().is_err(), "{}", invalid);
+        }
+        let err = zip_input(r#"expand = "zip"
+            args = { a = ["1", "2"], b = ["1"] }"#).validate().unwrap_err();
+        assert!(err.contains("a has 2, b has 1"), "{}", err);
+    }
 }
@@ -3,14 +3,43 @@ use concurrency::ConcurrencyGroup;
 mod condition;
 use condition::Condition;
 mod cmd;
-use cmd::{Cmd, ExecStatus};
+use cmd::{Cmd, CmdRet, ExecStatus};
 mod test;
-use test::{Test, TestResult};
+#[cfg(unix)]
+pub use test::DeathCase;
+#[cfg(unix)]
+use test::ChildVerdict;
+use test::{is_var_name, refers_var, replace_cmd_vars, Test, TestResult};
 mod config;
 pub use config::Config;
 mod input;
-use input::{ArgValue, InputGroup};
+use input::{ArgValue, Expand, InputGroup};
+mod covering;
+mod data;
+mod vars;
+pub use vars::Vars;
+use covering::covering_array;
 mod thread_info;
 pub use thread_info::ThreadInfo;
 mod env;
-pub use env::{Env, ResourceEnv};
+pub use env::{DeathMode, Env, ResourceEnv, RunSettings};
+#[cfg(unix)]
+mod child;
+mod executor;
+pub use executor::Executor;
+#[cfg(unix)]
+mod process;
+mod crash;
+mod soak;
+mod bench;
+use bench::BenchConfig;
+mod fuzz;
+use fuzz::FuzzCampaign;
+#[cfg(unix)]
+mod minimize;
+mod limits;
+use limits::ResourceLimits;
+#[cfg(unix)]
+pub use crash::install_crash_handler;
+mod filter;
+pub use filter::{NameFilter, NamePattern, TagFilter};
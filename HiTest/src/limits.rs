@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+
+/// Resource limits applied to the child process of a death test or an
+/// isolated test, so a broken SDK cannot take the whole machine down.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct ResourceLimits {
+    // address space in MB
+    #[serde(default)]
+    pub as_mb: Option<u64>,
+    // max number of open files
+    #[serde(default)]
+    pub nofile: Option<u64>,
+    // cpu time in seconds
+    #[serde(default)]
+    pub cpu_s: Option<u64>,
+    // whether a crash may write a core file
+    #[serde(default)]
+    pub core: Option<bool>,
+}
+
+impl ResourceLimits {
+    /// Fill the limits not given here from `default`.
+    pub fn or(&self, default: &ResourceLimits) -> ResourceLimits {
+        ResourceLimits {
+            as_mb: self.as_mb.or(default.as_mb),
+            nofile: self.nofile.or(default.nofile),
+            cpu_s: self.cpu_s.or(default.cpu_s),
+            core: self.core.or(default.core),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == ResourceLimits::default()
+    }
+}
+
+impl std::fmt::Display for ResourceLimits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut limits = Vec::new();
+        if let Some(as_mb) = self.as_mb {
+            limits.push(format!("as_mb={}", as_mb));
+        }
+        if let Some(nofile) = self.nofile {
+            limits.push(format!("nofile={}", nofile));
+        }
+        if let Some(cpu_s) = self.cpu_s {
+            limits.push(format!("cpu_s={}", cpu_s));
+        }
+        if let Some(core) = self.core {
+            limits.push(format!("core={}", core));
+        }
+        write!(f, "{{ {} }}", limits.join(", "))
+    }
+}
+
+#[cfg(unix)]
+mod apply {
+    use super::ResourceLimits;
+    use log::warn;
+    use nix::errno::Errno;
+    use nix::sys::resource::{getrlimit, setrlimit, Resource};
+    use nix::sys::signal::Signal;
+    use nix::sys::wait::WaitStatus;
+    use std::time::Duration;
+
+    fn set_soft_limit(resource: Resource, limit: u64) {
+        let result = getrlimit(resource).and_then(|(_, hard)| {
+            setrlimit(resource, limit.min(hard), hard)
+        });
+        if let Err(e) = result {
+            warn!("failed to set {:?} to {}: {}", resource, limit, e);
+        }
+    }
+
+    impl ResourceLimits {
+        /// Called in the child before running the test.
+        pub fn apply(&self) {
+            if let Some(as_mb) = self.as_mb {
+                set_soft_limit(Resource::RLIMIT_AS, as_mb.saturating_mul(1024 * 1024));
+            }
+            if let Some(nofile) = self.nofile {
+                set_soft_limit(Resource::RLIMIT_NOFILE, nofile);
+            }
+            if let Some(cpu_s) = self.cpu_s {
+                // SIGXCPU at the soft limit, SIGKILL one second later
+                let result = getrlimit(Resource::RLIMIT_CPU).and_then(|(_, hard)| {
+                    setrlimit(Resource::RLIMIT_CPU, cpu_s.min(hard), (cpu_s + 1).min(hard))
+                });
+                if let Err(e) = result {
+                    warn!("failed to set RLIMIT_CPU to {}: {}", cpu_s, e);
+                }
+            }
+            match self.core {
+                Some(false) => set_soft_limit(Resource::RLIMIT_CORE, 0),
+                Some(true) => set_soft_limit(Resource::RLIMIT_CORE, u64::MAX),
+                None => {}
+            }
+        }
+
+        /// Called in the child after a cmd failed, tells whether it ran out
+        /// of memory or open files. `errno` is the one set by the failed
+        /// call itself, see `CmdRet`.
+        pub fn exhausted(&self, errno: Errno) -> Option<String> {
+            match (errno, self.as_mb, self.nofile) {
+                (Errno::ENOMEM, Some(as_mb), _) => Some(format!("address space limit of {} MB", as_mb)),
+                (Errno::EMFILE, _, Some(nofile)) => Some(format!("open files limit of {}", nofile)),
+                _ => None,
+            }
+        }
+
+        /// Whether the child was killed for running out of cpu time, it used
+        /// `cpu_time` in total. A SIGKILL is the hard limit only when the
+        /// child did use up its cpu time, anyone else may send it too.
+        pub fn cpu_exceeded(&self, status: WaitStatus, cpu_time: Duration) -> bool {
+            match (self.cpu_s, status) {
+                (Some(_), WaitStatus::Signaled(_, Signal::SIGXCPU, _)) => true,
+                (Some(cpu_s), WaitStatus::Signaled(_, Signal::SIGKILL, _)) => cpu_time >= Duration::from_secs(cpu_s),
+                _ => false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_or() {
+        let default: ResourceLimits = toml::from_str("as_mb = 512\nnofile = 64\ncore = false").unwrap();
+        let limits: ResourceLimits = toml::from_str("nofile = 16\ncpu_s = 5").unwrap();
+        let merged = limits.or(&default);
+        assert_eq!(merged.as_mb, Some(512));
+        assert_eq!(merged.nofile, Some(16));
+        assert_eq!(merged.cpu_s, Some(5));
+        assert_eq!(merged.core, Some(false));
+        assert!(ResourceLimits::default().is_empty());
+        assert!(!merged.is_empty());
+        assert_eq!(merged.to_string(), "{ as_mb=512, nofile=16, cpu_s=5, core=false }");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_limit_hit() {
+        use nix::errno::Errno;
+        use nix::sys::signal::Signal;
+        use nix::sys::wait::WaitStatus;
+        use nix::unistd::Pid;
+        use std::time::Duration;
+
+        let limits: ResourceLimits = toml::from_str("nofile = 16\ncpu_s = 2").unwrap();
+        assert_eq!(limits.exhausted(Errno::EMFILE).unwrap(), "open files limit of 16");
+        // no address space limit to run out of
+        assert_eq!(limits.exhausted(Errno::ENOMEM), None);
+        assert_eq!(limits.exhausted(Errno::EBADF), None);
+
+        let killed = |signal| WaitStatus::Signaled(Pid::from_raw(1), signal, false);
+        assert!(limits.cpu_exceeded(killed(Signal::SIGXCPU), Duration::ZERO));
+        assert!(limits.cpu_exceeded(killed(Signal::SIGKILL), Duration::from_secs(3)));
+        // killed by someone else before using up its cpu time
+        assert!(!limits.cpu_exceeded(killed(Signal::SIGKILL), Duration::from_millis(10)));
+        assert!(!limits.cpu_exceeded(killed(Signal::SIGSEGV), Duration::from_secs(3)));
+        assert!(!ResourceLimits::default().cpu_exceeded(killed(Signal::SIGXCPU), Duration::ZERO));
+    }
+}
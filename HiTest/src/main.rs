@@ -1,9 +1,11 @@
 use libparser::*;
 use log::warn;
 use std::fs;
+use std::io::Read;
 mod args;
 use args::RunArgs;
-use hitest::Config;
+use hitest::{Config, Vars};
+use hitest::{NameFilter, TagFilter};
 use hitest::ThreadInfo;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -16,16 +18,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         std::env::set_var("RUST_LOG", run_args.log_lvl);
     }
     env_logger::init();
+    #[cfg(unix)]
+    hitest::install_crash_handler();
 
-    // loading libraries
     let lib_cfg_path: std::path::PathBuf = std::env::current_dir()
         .unwrap()
         .canonicalize()
         .unwrap()
         .join(&run_args.libs_cfg);
-
     let lib_cfg_path = lib_cfg_path.to_str().unwrap();
-    LibParse::init(&lib_cfg_path).unwrap();
+
+    // started by a death test in exec mode, run it and report by exit code
+    #[cfg(unix)]
+    if run_args.death_case {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        let case: hitest::DeathCase = toml::from_str(&input)?;
+        LibParse::init(lib_cfg_path).unwrap();
+        std::process::exit(case.run());
+    }
 
     // checking config file of test cases
     let config_content: String = fs::read_to_string(&run_args.test_cfg).expect(&format!(
@@ -45,6 +56,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    config.load_data(&run_args.test_cfg)?;
+    config.apply_vars(Vars::load(&run_args.vars_files, &run_args.defines)?);
+
     // run test cases
 
     if config.debug_test.is_none() {
@@ -57,6 +71,68 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    config.tag_filter = TagFilter::new(&run_args.tags, &run_args.exclude_tags);
+    if !config.tag_filter.is_empty() {
+        warn!(
+            "tag filter is set, include: {:?}, exclude: {:?}",
+            &run_args.tags, &run_args.exclude_tags
+        );
+    }
+    config.name_filter = NameFilter::new(&run_args.filters, &run_args.skips)?;
+
+    if run_args.list {
+        config.list();
+        return Ok(());
+    }
+
+    // loading libraries
+    LibParse::init(&lib_cfg_path).unwrap();
+
+    if let Some(retries) = run_args.retries {
+        config.retries = retries;
+    }
+
+    if run_args.max_failures.is_some() {
+        config.max_failures = run_args.max_failures;
+    }
+
+    if run_args.seed.is_some() {
+        config.seed = run_args.seed;
+    }
+    if run_args.shuffle {
+        config.shuffle = true;
+    }
+
+    if run_args.death_timeout_ms.is_some() {
+        config.death_timeout_ms = run_args.death_timeout_ms;
+    }
+
+    if let Some(ref mode) = run_args.death_mode {
+        config.death_mode = mode.parse()?;
+    }
+    if run_args.isolate {
+        config.isolate = true;
+    }
+    if let Some(ref executor) = run_args.executor {
+        config.executor = executor.parse()?;
+    }
+    if run_args.soak.is_some() {
+        config.soak = run_args.soak;
+    }
+    if run_args.soak_interval.is_some() {
+        config.soak_interval = run_args.soak_interval;
+    }
+    config.fuzz_mode = run_args.fuzz;
+    if run_args.bench_csv.is_some() {
+        config.bench_csv = run_args.bench_csv;
+    }
+    config.libs_cfg = lib_cfg_path.to_string();
+
+    #[cfg(unix)]
+    if let Some(ref name) = run_args.minimize {
+        return config.minimize(name, run_args.minimize_output.as_deref());
+    }
+
     if !config.default_serial {
         config.default_serial = run_args.serial;
     }
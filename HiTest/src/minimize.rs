@@ -0,0 +1,236 @@
+use super::child::ChildReport;
+use super::{ChildVerdict, Cmd, Test};
+use log::{debug, info, warn};
+use std::fmt;
+
+/// How a test failed, a candidate is kept only when it fails the same way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    verdict: ChildVerdict,
+    // opfunc and actual return value of the first failed check
+    check: Option<(String, i64)>,
+}
+
+impl Failure {
+    // None when the child did not fail
+    fn new(verdict: ChildVerdict, report: &ChildReport) -> Option<Self> {
+        if !verdict.is_failure() {
+            return None;
+        }
+        let check = match verdict {
+            ChildVerdict::Failed => report.check.clone(),
+            _ => None,
+        };
+        Some(Failure { verdict, check })
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.check {
+            Some((ref opfunc, actual)) => write!(f, "{}: {} returned {}", self.verdict, opfunc, actual),
+            None => write!(f, "{}", self.verdict),
+        }
+    }
+}
+
+/// The smallest sublist of `items` found by delta debugging for which
+/// `reproduces` still holds, removing a single item from it does not.
+pub fn ddmin<T: Clone, F: FnMut(&[T]) -> bool>(mut items: Vec<T>, mut reproduces: F) -> Vec<T> {
+    let mut chunks = 2;
+    while items.len() >= 2 {
+        let size = items.len().div_ceil(chunks);
+        let mut reduced = false;
+        for start in (0..items.len()).step_by(size) {
+            let end = (start + size).min(items.len());
+            let candidate: Vec<T> = items[..start].iter().chain(&items[end..]).cloned().collect();
+            if reproduces(&candidate) {
+                items = candidate;
+                chunks = (chunks - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+        if !reduced {
+            if chunks >= items.len() {
+                break;
+            }
+            chunks = (chunks * 2).min(items.len());
+        }
+    }
+    items
+}
+
+/// Simpler values to try for an arg value, the simplest first: 0, 1 and the
+/// half for numbers, empty and the first half for quoted strings. Other
+/// values, such as `@shared[N]`, are kept.
+pub fn simpler_values(value: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    // smaller in magnitude only, or 0 and 1 would replace each other forever
+    let smaller = |n: i64| [0, 1, n / 2].into_iter().filter(move |v| v.unsigned_abs() < n.unsigned_abs());
+    if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        if let Ok(n @ 0..) = i64::from_str_radix(hex, 16) {
+            values = smaller(n).map(|v| format!("{:#x}", v)).collect();
+        }
+    } else if let Ok(n) = value.parse::<i64>() {
+        values = smaller(n).map(|v| v.to_string()).collect();
+    } else if let Some(s) = value.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        let half: String = s.chars().take(s.chars().count() / 2).collect();
+        values = vec!["''".to_string(), format!("'{}'", half)];
+    }
+    let mut seen = vec![value.to_string()];
+    values.retain(|v| {
+        let new = !seen.contains(v);
+        seen.push(v.clone());
+        new
+    });
+    values
+}
+
+/// A failing test shrunk by `minimize`.
+#[derive(Debug)]
+pub struct Minimized {
+    pub test: Test,
+    pub failure: Failure,
+    // child processes run to find it
+    pub runs: usize,
+}
+
+struct Minimizer<'a> {
+    test: &'a Test,
+    failure: Failure,
+    runs: usize,
+}
+
+impl Minimizer<'_> {
+    fn with_cmds(&self, cmds: &[Cmd]) -> Test {
+        let mut test = self.test.clone();
+        test.cmds = cmds.to_vec();
+        test
+    }
+
+    fn reproduces(&mut self, test: &Test) -> bool {
+        self.runs += 1;
+        match test.probe() {
+            Ok((verdict, report)) => {
+                let failure = Failure::new(verdict, &report);
+                let ended = failure.as_ref().map_or(verdict.to_string(), |f| f.to_string());
+                debug!("Candidate {} of {}: {}", self.runs, test.name, ended);
+                failure.as_ref() == Some(&self.failure)
+            }
+            Err(e) => {
+                warn!("Failed to run a candidate of {}: {}", test.name, e);
+                false
+            }
+        }
+    }
+
+    fn shrink_cmds(&mut self, cmds: Vec<Cmd>) -> Vec<Cmd> {
+        ddmin(cmds, |cmds| {
+            let test = self.with_cmds(cmds);
+            self.reproduces(&test)
+        })
+    }
+
+    // replace every arg value by the simplest one still failing the same way
+    fn simplify_args(&mut self, mut cmds: Vec<Cmd>) -> Vec<Cmd> {
+        for i in 0..cmds.len() {
+            for j in 0..cmds[i].args.len() {
+                let Some((key, mut value)) = cmds[i].args[j]
+                    .split_once('=')
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                else {
+                    continue;
+                };
+                'simplify: loop {
+                    for candidate in simpler_values(&value) {
+                        cmds[i].args[j] = format!("{}={}", key, candidate);
+                        let test = self.with_cmds(&cmds);
+                        if self.reproduces(&test) {
+                            value = candidate;
+                            continue 'simplify;
+                        }
+                    }
+                    cmds[i].args[j] = format!("{}={}", key, value);
+                    break;
+                }
+            }
+        }
+        cmds
+    }
+}
+
+/// Shrink a failing test to fewer cmds and simpler arg values, running every
+/// candidate once in a child process. A candidate is kept when it ends with
+/// the same verdict, and for a failed check with the same opfunc returning
+/// the same value.
+pub fn minimize(test: &Test) -> Result<Minimized, Box<dyn std::error::Error>> {
+    if test.thread_num > 1 {
+        warn!("Test case {} runs on {} threads, minimize runs it on one.", test.name, test.thread_num);
+    }
+    let (verdict, report) = test.probe()?;
+    let failure = Failure::new(verdict, &report)
+        .ok_or_else(|| format!("test case {} {}, nothing to minimize", test.name, verdict))?;
+    info!("Minimize test case {} with {} cmds, it {}.", test.name, test.cmds.len(), failure);
+
+    let mut minimizer = Minimizer {
+        test,
+        failure,
+        runs: 1,
+    };
+    let cmds = minimizer.shrink_cmds(test.cmds.clone());
+    info!("Test case {} still fails with {} cmds, simplify the args.", test.name, cmds.len());
+    let cmds = minimizer.simplify_args(cmds);
+    Ok(Minimized {
+        test: minimizer.with_cmds(&cmds),
+        failure: minimizer.failure,
+        runs: minimizer.runs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ddmin() {
+        let items: Vec<i32> = (0..20).collect();
+        let mut runs = 0;
+        let min = ddmin(items, |items| {
+            runs += 1;
+            items.contains(&3) && items.contains(&17)
+        });
+        assert_eq!(min, [3, 17]);
+        assert!(runs < 50, "{} runs", runs);
+
+        assert_eq!(ddmin(vec![1, 2, 3], |items| items.len() == 3), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_simpler_values() {
+        assert_eq!(simpler_values("100"), ["0", "1", "50"]);
+        assert_eq!(simpler_values("2"), ["0", "1"]);
+        assert_eq!(simpler_values("1"), ["0"]);
+        assert!(simpler_values("0").is_empty());
+        assert_eq!(simpler_values("-1"), ["0"]);
+        assert_eq!(simpler_values("-9"), ["0", "1", "-4"]);
+        assert_eq!(simpler_values("0x1000"), ["0x0", "0x1", "0x800"]);
+        assert_eq!(simpler_values("'abcd'"), ["''", "'ab'"]);
+        assert_eq!(simpler_values("'a'"), ["''"]);
+        assert!(simpler_values("''").is_empty());
+        assert!(simpler_values("@shared[3]").is_empty());
+    }
+
+    #[test]
+    fn test_failure_signature() {
+        let report = ChildReport {
+            check: Some(("Call_f".to_string(), 5)),
+            finished: true,
+        };
+        let failure = Failure::new(ChildVerdict::Failed, &report).unwrap();
+        assert_eq!(failure.to_string(), "failed: Call_f returned 5");
+        assert_ne!(Failure::new(ChildVerdict::Failed, &ChildReport::default()), Some(failure));
+        assert_eq!(Failure::new(ChildVerdict::Passed, &report), None);
+        assert_eq!(Failure::new(ChildVerdict::Timeout, &report).unwrap().to_string(), "timed out");
+    }
+}
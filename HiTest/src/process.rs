@@ -0,0 +1,139 @@
+use super::child::{wait_child, ChildExit, ChildPipe};
+use super::{executor, ResourceEnv, TestResult};
+use log::{error, info};
+use nix::sys::wait::WaitStatus;
+use nix::unistd::{fork, write, ForkResult, Pid};
+use std::panic;
+use std::process::exit;
+
+// the worker side, never returns
+fn run_worker<F: Fn() -> TestResult>(pipe: ChildPipe, expected: usize, f: &F) -> ! {
+    let fd = pipe.into_writer();
+    executor::mark_forked();
+    let process_env = ResourceEnv::get_instance()
+        .and_then(|instance| instance.read().unwrap().process_env.clone());
+    if let Some(process_env) = &process_env {
+        process_env.apply_env_init();
+    }
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(f)).unwrap_or_else(|_| TestResult {
+        failed: expected,
+        ..Default::default()
+    });
+    if let Some(process_env) = &process_env {
+        process_env.apply_env_exit();
+    }
+    let output = toml::to_string(&res).unwrap_or_default();
+    let mut bytes = output.as_bytes();
+    while !bytes.is_empty() {
+        match write(fd, bytes) {
+            Ok(n) => bytes = &bytes[n..],
+            Err(nix::errno::Errno::EINTR) => continue,
+            Err(_) => break,
+        }
+    }
+    exit(0);
+}
+
+/// Run `f` in `process_num` forked worker processes at the same time, each
+/// with its own process_env init and exit, and merge their results.
+///
+/// `expected` is the number of test cases one worker runs, they are counted
+/// as failed when the worker crashes.
+pub fn run_in_processes<F: Fn() -> TestResult>(
+    name: &str,
+    process_num: usize,
+    expected: usize,
+    f: F,
+) -> TestResult {
+    info!("Run {} in {} processes!", name, process_num);
+    let crashed = TestResult {
+        failed: expected,
+        ..Default::default()
+    };
+
+    let mut workers: Vec<Option<(Pid, ChildPipe)>> = Vec::new();
+    for _ in 0..process_num {
+        let pipe = match ChildPipe::new() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                error!("Failed to create result pipe of {}: {}", name, e);
+                workers.push(None);
+                continue;
+            }
+        };
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => run_worker(pipe, expected, &f),
+            Ok(ForkResult::Parent { child }) => workers.push(Some((child, pipe))),
+            Err(e) => {
+                error!("Failed to fork worker process of {}: {}", name, e);
+                workers.push(None);
+            }
+        }
+    }
+
+    let mut result = TestResult::default();
+    for (i, worker) in workers.into_iter().enumerate() {
+        let res = match worker {
+            Some((child, pipe)) => match wait_child(child, None, Some(pipe)) {
+                Ok(ChildExit {
+                    status: Some(WaitStatus::Exited(_, 0)),
+                    output,
+                    ..
+                }) => toml::from_str(&output).unwrap_or_else(|e| {
+                    error!("{} process {} sent a broken result: {}", name, i, e);
+                    TestResult {
+                        failed: expected,
+                        ..Default::default()
+                    }
+                }),
+                Ok(ChildExit {
+                    status: Some(WaitStatus::Signaled(_, signal, core_dumped)),
+                    ..
+                }) => {
+                    let core = if core_dumped { " (core dumped)" } else { "" };
+                    error!("{} process {} crashed with signal {}{}.", name, i, signal, core);
+                    crashed.clone()
+                }
+                Ok(ChildExit { status, .. }) => {
+                    error!("{} process {} finished unexpectedly: {:?}", name, i, status);
+                    crashed.clone()
+                }
+                Err(e) => {
+                    error!("Waitpid error: {}", e);
+                    crashed.clone()
+                }
+            },
+            None => crashed.clone(),
+        };
+        result.merge(&res);
+    }
+    // the failures of the workers are not seen by this process
+    for _ in 0..result.failed {
+        ResourceEnv::record_failure();
+    }
+    if result.failed > 0 {
+        error!("{} execute finished in {} processes! {}\n", name, process_num, result);
+    } else {
+        info!("{} execute finished in {} processes! {}\n", name, process_num, result);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_in_processes() {
+        let res = run_in_processes("workers", 3, 2, || TestResult {
+            passed: 1,
+            skipped: 1,
+            ..Default::default()
+        });
+        assert_eq!((res.passed, res.skipped, res.failed), (3, 3, 0));
+
+        // a crashed worker fails all of its test cases
+        let res = run_in_processes("crash", 2, 2, || -> TestResult { std::process::abort() });
+        assert_eq!((res.passed, res.failed), (0, 4));
+    }
+}
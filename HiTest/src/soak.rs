@@ -0,0 +1,256 @@
+use super::{ResourceEnv, TestResult};
+use log::{error, info};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_SOAK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Parse a duration such as "8h", "1h30m", "90s" or "500ms", a plain number
+/// is in seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let invalid = || format!("invalid duration '{}', expect such as 8h, 30m, 1h30m, 90s or 500ms", s);
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    if let Ok(secs) = s.parse::<u64>() {
+        total = Duration::from_secs(secs);
+        rest = "";
+    }
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let value: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = &rest[digits..];
+        let unit = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        total += match &rest[..unit] {
+            "ms" => Duration::from_millis(value),
+            "s" => Duration::from_secs(value),
+            "m" => Duration::from_secs(value * 60),
+            "h" => Duration::from_secs(value * 3600),
+            "d" => Duration::from_secs(value * 86400),
+            _ => return Err(invalid()),
+        };
+        rest = &rest[unit..];
+    }
+    if total.is_zero() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+// hh:mm:ss, or milliseconds below one second
+fn format_duration(d: Duration) -> String {
+    if d < Duration::from_secs(1) {
+        return format!("{}ms", d.as_millis());
+    }
+    let secs = d.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Resource usage of this process, to find leaks over a soak run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessStats {
+    pub rss_kb: Option<u64>,
+    pub fds: Option<usize>,
+}
+
+impl ProcessStats {
+    pub fn current() -> Self {
+        ProcessStats {
+            rss_kb: Self::rss_kb(),
+            fds: Self::fds(),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn rss_kb() -> Option<u64> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+        line.split_whitespace().nth(1)?.parse().ok()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn rss_kb() -> Option<u64> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn fds() -> Option<usize> {
+        // the descriptor of the opened dir itself is listed too
+        let count = std::fs::read_dir("/dev/fd").ok()?.count();
+        Some(count.saturating_sub(1))
+    }
+
+    #[cfg(not(unix))]
+    fn fds() -> Option<usize> {
+        None
+    }
+}
+
+// value and growth since the start, "n/a" when not supported
+fn growth(now: Option<u64>, base: Option<u64>, unit: &str) -> String {
+    match (now, base) {
+        (Some(now), Some(base)) => format!("{}{} ({:+}{})", now, unit, now as i64 - base as i64, unit),
+        (Some(now), None) => format!("{}{}", now, unit),
+        _ => "n/a".to_string(),
+    }
+}
+
+fn failure_rate(res: &TestResult) -> f64 {
+    let run = res.passed + res.failed;
+    if run == 0 {
+        return 0.0;
+    }
+    res.failed as f64 * 100.0 / run as f64
+}
+
+/// Tracks the iterations of a soak run and reports them periodically.
+struct Soak<'a> {
+    name: &'a str,
+    duration: Duration,
+    start: Instant,
+    base: ProcessStats,
+    iterations: usize,
+    total: TestResult,
+    // since the last report
+    window_iterations: usize,
+    window: TestResult,
+}
+
+impl Soak<'_> {
+    fn record(&mut self, res: &TestResult) {
+        self.iterations += 1;
+        self.window_iterations += 1;
+        self.total.merge(res);
+        self.window.merge(res);
+    }
+
+    fn stats(&self) -> String {
+        let now = ProcessStats::current();
+        format!(
+            "rss: {}, fds: {}",
+            growth(now.rss_kb, self.base.rss_kb, " KB"),
+            growth(now.fds.map(|fds| fds as u64), self.base.fds.map(|fds| fds as u64), "")
+        )
+    }
+
+    fn report(&mut self) {
+        println!(
+            "[soak] {} {}/{} iterations: {} (+{}), failure rate: {:.2}% (total {:.2}%), {}",
+            self.name,
+            format_duration(self.start.elapsed()),
+            format_duration(self.duration),
+            self.iterations,
+            self.window_iterations,
+            failure_rate(&self.window),
+            failure_rate(&self.total),
+            self.stats()
+        );
+        self.window_iterations = 0;
+        self.window = TestResult::default();
+    }
+
+    fn finish(self) -> TestResult {
+        let summary = format!(
+            "Soak {} finished after {}, {} iterations, failure rate: {:.2}%, {}! {}\n",
+            self.name,
+            format_duration(self.start.elapsed()),
+            self.iterations,
+            failure_rate(&self.total),
+            self.stats(),
+            self.total
+        );
+        if self.total.failed > 0 {
+            error!("{}", summary);
+        } else {
+            info!("{}", summary);
+        }
+        self.total
+    }
+}
+
+/// Run `f` again and again until `duration` expires or the run stops,
+/// reporting the iterations, failure rate and resource growth every
+/// `interval`. Returns the results of all iterations merged, see
+/// `TestResult::aggregate` to count them as one test case.
+///
+/// The report is printed between iterations, so one longer than `interval`
+/// delays it.
+pub fn run_soak<F: FnMut() -> TestResult>(
+    name: &str,
+    duration: Duration,
+    interval: Duration,
+    mut f: F,
+) -> TestResult {
+    info!("Soak {} for {}, report every {}.", name, format_duration(duration), format_duration(interval));
+    let mut soak = Soak {
+        name,
+        duration,
+        start: Instant::now(),
+        base: ProcessStats::current(),
+        iterations: 0,
+        total: TestResult::default(),
+        window_iterations: 0,
+        window: TestResult::default(),
+    };
+    let mut next_report = soak.start + interval;
+    while soak.start.elapsed() < duration && !ResourceEnv::should_stop() {
+        let res = f();
+        soak.record(&res);
+        if Instant::now() >= next_report {
+            soak.report();
+            next_report = Instant::now() + interval;
+        }
+    }
+    soak.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("8h"), Ok(Duration::from_secs(8 * 3600)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(2 * 86400)));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_duration("8x").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("0").is_err());
+        assert!(parse_duration("").is_err());
+        assert_eq!(format_duration(Duration::from_secs(5400 + 7)), "01:30:07");
+        assert_eq!(format_duration(Duration::from_millis(250)), "250ms");
+        assert_eq!(format_duration(Duration::from_millis(1500)), "00:00:01");
+    }
+
+    #[test]
+    fn test_run_soak() {
+        let mut iterations = 0;
+        let res = run_soak("loop", Duration::from_millis(50), Duration::from_millis(10), || {
+            iterations += 1;
+            std::thread::sleep(Duration::from_millis(5));
+            TestResult {
+                passed: 2,
+                failed: iterations % 2,
+                ..Default::default()
+            }
+        });
+        assert!(iterations > 1);
+        assert_eq!(res.passed, iterations * 2);
+        assert_eq!(res.failed, iterations.div_ceil(2));
+        // counted once in the run's summary
+        let verdict = res.aggregate();
+        assert_eq!((verdict.total(), verdict.failed), (1, 1));
+    }
+
+    #[test]
+    fn test_process_stats() {
+        let stats = ProcessStats::current();
+        #[cfg(target_os = "linux")]
+        assert!(stats.rss_kb.unwrap() > 0);
+        #[cfg(unix)]
+        assert!(stats.fds.unwrap() >= 3);
+    }
+}
@@ -1,15 +1,22 @@
-use super::{ArgValue, Cmd, ExecStatus, Condition, InputGroup, ResourceEnv, ThreadInfo};
+use super::soak::{parse_duration, run_soak};
+use super::bench::run_bench;
+use super::{BenchConfig, crash, executor, executor::SyncPoint, ArgValue, Cmd, CmdRet, DeathMode, Env, ExecStatus, Expand, Condition, InputGroup, covering_array, NameFilter, ResourceEnv, ResourceLimits, TagFilter, ThreadInfo};
 use log::{debug, error, info, warn};
 #[cfg(unix)]
-use nix::{sys::wait::waitpid, sys::wait::WaitStatus, unistd::fork, unistd::ForkResult};
+use nix::{sys::signal::Signal, sys::wait::WaitStatus, unistd::fork, unistd::ForkResult, unistd::Pid};
 use rand::seq::SliceRandom;
-use rand::thread_rng;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::{fmt, panic};
 #[cfg(unix)]
-use std::process::exit;
+use std::io::Write;
+#[cfg(unix)]
+use std::process::{exit, Command, Stdio};
+use std::time::Duration;
+#[cfg(unix)]
+use super::child::{self, wait_child_with_report, ChildExit, ChildPipe, ChildReport};
+use regex::Regex;
 use thiserror::Error;
 
 fn default_true() -> bool {
@@ -20,7 +27,7 @@ fn default_one() -> i64 {
     1
 }
 
-#[derive(Debug, Deserialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct Test {
     pub name: String,
     pub cmds: Vec<Cmd>,
@@ -30,16 +37,154 @@ pub struct Test {
     pub should_panic: bool,
     #[serde(default = "default_true")]
     pub break_if_fail: bool,
-    #[serde(default)]
+    // already expanded into cmds when a test is sent to an exec child
+    #[serde(default, skip_serializing)]
     pub inputs: Vec<InputGroup>,
     #[serde(default)]
     pub serial: Option<bool>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub disabled: Option<String>,
+    #[serde(default)]
+    pub xfail: Option<String>,
+    #[serde(default)]
+    pub retries: Option<usize>,
+    #[serde(default)]
+    pub expect_signal: Option<String>,
+    #[serde(default)]
+    pub expect_exit_code: Option<i32>,
+    #[serde(default)]
+    pub expect_stderr: Option<String>,
+    #[serde(default)]
+    pub death_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub death_mode: Option<DeathMode>,
+    #[serde(default)]
+    pub isolate: Option<bool>,
+    #[serde(default)]
+    pub limits: Option<ResourceLimits>,
+    #[serde(default)]
+    pub process_num: Option<usize>,
+    #[serde(default)]
+    pub sync_start: bool,
+    // loop the test until this much time passed, such as "8h"
+    #[serde(default)]
+    pub duration: Option<String>,
+    // measure the throughput with these thread counts instead of running thread_num
+    #[serde(default)]
+    pub bench: Option<BenchConfig>,
+    // shared by the threads running this test at the same time
+    #[serde(skip)]
+    pub sync: Option<Arc<SyncPoint>>,
+    // the first and last cmds added by envs, not timed by a benchmark
+    #[serde(skip)]
+    pub env_init_len: usize,
+    #[serde(skip)]
+    pub env_exit_len: usize,
 }
-#[derive(Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct TestResult {
     pub passed: usize,
     pub failed: usize,
     pub skipped: usize,
+    pub disabled: usize,
+    pub xfail: usize,
+    pub xpass: usize,
+    // passed after at least one retry, already counted in passed
+    pub flaky: usize,
+    // not run because the run stopped early
+    pub not_run: usize,
+    pub not_run_tests: Vec<String>,
+}
+
+// outcome of executing one test clone
+enum Outcome {
+    // final status and whether it was retried
+    Done(ExecStatus, bool),
+    NotRun(String),
+}
+
+impl TestResult {
+    pub fn merge(&mut self, other: &TestResult) {
+        self.passed += other.passed;
+        self.failed += other.failed;
+        self.skipped += other.skipped;
+        self.disabled += other.disabled;
+        self.xfail += other.xfail;
+        self.xpass += other.xpass;
+        self.flaky += other.flaky;
+        self.not_run += other.not_run;
+        for name in &other.not_run_tests {
+            if !self.not_run_tests.contains(name) {
+                self.not_run_tests.push(name.clone());
+            }
+        }
+    }
+
+    pub fn add_not_run(&mut self, name: String, count: usize) {
+        self.not_run += count;
+        if !self.not_run_tests.contains(&name) {
+            self.not_run_tests.push(name);
+        }
+    }
+
+    /// The results of the iterations of a soak run merged, counted as one
+    /// test case with the worst verdict.
+    pub fn aggregate(&self) -> TestResult {
+        let mut res = TestResult::default();
+        if self.failed > 0 {
+            res.failed = 1;
+        } else if self.xpass > 0 {
+            res.xpass = 1;
+        } else if self.passed > 0 {
+            res.passed = 1;
+            res.flaky = (self.flaky > 0) as usize;
+        } else if self.xfail > 0 {
+            res.xfail = 1;
+        } else if self.skipped > 0 {
+            res.skipped = 1;
+        } else if self.disabled > 0 {
+            res.disabled = 1;
+        } else if self.not_run > 0 {
+            res.not_run = 1;
+            res.not_run_tests = self.not_run_tests.clone();
+        }
+        res
+    }
+
+    pub fn total(&self) -> usize {
+        self.passed
+            + self.failed
+            + self.skipped
+            + self.disabled
+            + self.xfail
+            + self.xpass
+            + self.not_run
+    }
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Passed: {}, Failed: {}, Skipped: {}",
+            self.passed, self.failed, self.skipped
+        )?;
+        if self.flaky > 0 {
+            write!(f, " ({} passed after retry)", self.flaky)?;
+        }
+        if self.disabled > 0 {
+            write!(f, ", Disabled: {}", self.disabled)?;
+        }
+        if self.xfail > 0 || self.xpass > 0 {
+            write!(f, ", XFail: {}, XPass: {}", self.xfail, self.xpass)?;
+        }
+        if self.not_run > 0 {
+            write!(f, ", Not Run: {}", self.not_run)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -49,96 +194,601 @@ pub enum TestError {
 
     #[error("Shared input group '{0}' not found")]
     SharedInputNotFound(String),
+
+    #[error("Invalid expect_signal '{0}' of test '{1}'")]
+    InvalidSignal(String, String),
+
+    #[error("expect_signal and expect_exit_code of test '{0}' are mutually exclusive")]
+    ConflictExpect(String),
+
+    #[error("Invalid expect_stderr of test '{0}': {1}")]
+    InvalidStderr(String, String),
+
+    #[error("Invalid duration of test '{0}': {1}")]
+    InvalidDuration(String, String),
+
+    #[error("Invalid bench of test '{0}': {1}")]
+    InvalidBench(String, String),
+
+    #[error("Invalid inputs of test '{0}': {1}")]
+    InvalidInput(String, String),
 }
 
 const EXIT_CODE_PASSED: i32 = 0;
 const EXIT_CODE_FAILED: i32 = 1;
 const EXIT_CODE_SKIPPED: i32 = 2;
+// the child ran out of a resource limited by `limits`
+const EXIT_CODE_LIMIT: i32 = 125;
+
+/// A death test sent to a fresh hitest process in exec mode, with the envs
+/// the forked child would apply.
+#[cfg(unix)]
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeathCase {
+    test: Test,
+    #[serde(default)]
+    limits: ResourceLimits,
+    #[serde(default)]
+    process_env: Option<Env>,
+    #[serde(default)]
+    thread_env: Option<Env>,
+    // the crash handler is not wanted when the test is meant to crash
+    #[serde(default)]
+    expect_crash: bool,
+    // the inherited write end of the report pipe of `Test::probe`
+    #[serde(default)]
+    report_fd: Option<i32>,
+}
+
+#[cfg(unix)]
+impl DeathCase {
+    /// Run the test once in this process, returns the exit code for the parent.
+    pub fn run(&self) -> i32 {
+        if self.expect_crash {
+            crash::reset_crash_handler();
+        }
+        if let Some(fd) = self.report_fd {
+            child::set_report_fd(fd);
+        }
+        if let Some(process_env) = &self.process_env {
+            process_env.apply_env_init();
+        }
+        if let Some(thread_env) = &self.thread_env {
+            thread_env.apply_env_init();
+        }
+        let code = self.test.run_in_child(&self.limits);
+        if let Some(thread_env) = &self.thread_env {
+            thread_env.apply_env_exit();
+        }
+        if let Some(process_env) = &self.process_env {
+            process_env.apply_env_exit();
+        }
+        code
+    }
+}
+
+/// How a test run once in a child process ended, see `Test::probe`.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildVerdict {
+    Passed,
+    Skipped,
+    // a cmd returned an unexpected value
+    Failed,
+    Exited(i32),
+    Signaled(Signal),
+    Timeout,
+}
+
+#[cfg(unix)]
+impl ChildVerdict {
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, ChildVerdict::Passed | ChildVerdict::Skipped)
+    }
+
+    // a child not reporting its cmds finished exited in the middle of them
+    fn from_exit(exit: &ChildExit) -> Result<Self, String> {
+        match exit.status {
+            None => Ok(ChildVerdict::Timeout),
+            Some(WaitStatus::Exited(_, code)) if !exit.report.finished => Ok(ChildVerdict::Exited(code)),
+            Some(WaitStatus::Exited(_, EXIT_CODE_PASSED)) => Ok(ChildVerdict::Passed),
+            Some(WaitStatus::Exited(_, EXIT_CODE_SKIPPED)) => Ok(ChildVerdict::Skipped),
+            Some(WaitStatus::Exited(_, EXIT_CODE_FAILED)) => Ok(ChildVerdict::Failed),
+            Some(WaitStatus::Exited(_, code)) => Ok(ChildVerdict::Exited(code)),
+            Some(WaitStatus::Signaled(_, signal, _)) => Ok(ChildVerdict::Signaled(signal)),
+            Some(status) => Err(format!("unexpected child status: {:?}", status)),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl fmt::Display for ChildVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChildVerdict::Passed => write!(f, "passed"),
+            ChildVerdict::Skipped => write!(f, "skipped"),
+            ChildVerdict::Failed => write!(f, "failed"),
+            ChildVerdict::Exited(code) => write!(f, "exited with code {}", code),
+            ChildVerdict::Signaled(signal) => write!(f, "crashed with {}", signal),
+            ChildVerdict::Timeout => write!(f, "timed out"),
+        }
+    }
+}
+
+// a test case file holding the given tests
+#[derive(Serialize)]
+struct CaseFile<'a> {
+    tests: [&'a Test; 1],
+}
 
 impl Test {
+    /// This test as a test case file, after the `header` comment lines.
+    pub fn to_case_file(&self, header: &str) -> Result<String, toml::ser::Error> {
+        let body = toml::to_string(&CaseFile { tests: [self] })?;
+        let header: String = header.lines().map(|line| format!("# {}\n", line)).collect();
+        Ok(format!("{}{}", header, body))
+    }
+
+    /// Run the cmds once in a child process, killed after the death timeout,
+    /// and tell how it ended with what the child reported. A child exiting
+    /// before its cmds all returned ends with `ChildVerdict::Exited`, even
+    /// with the exit code of a passed test.
+    #[cfg(unix)]
+    pub fn probe(&self) -> Result<(ChildVerdict, ChildReport), Box<dyn std::error::Error>> {
+        // the logs of the child are not wanted, only how it ended
+        let stderr_pipe = ChildPipe::new()?;
+        let report_pipe = ChildPipe::new()?;
+        let child = self.spawn_child(Some(&stderr_pipe), Some(&report_pipe), false)?;
+        let exit = wait_child_with_report(child, Some(self.death_timeout()), Some(stderr_pipe), Some(report_pipe))?;
+        let verdict = ChildVerdict::from_exit(&exit)?;
+        Ok((verdict, exit.report))
+    }
+
     #[cfg(unix)]
-    fn check_panic(mut child_test: Self) -> ExecStatus {
+    fn check_panic(child_test: Self) -> ExecStatus {
         info!(
             "start executing test case {} with panic check.",
             child_test.name
         );
-        match unsafe { fork() } {
-            Ok(ForkResult::Child) => {
-                let res_env = ResourceEnv::get_instance().unwrap().read().unwrap();
-                if let Some(process_env) = &res_env.process_env {
+        let stderr_pipe = match child_test.expect_stderr {
+            Some(_) => match ChildPipe::new() {
+                Ok(pipe) => Some(pipe),
+                Err(e) => {
+                    error!("Failed to create stderr pipe: {}", e);
+                    return ExecStatus::Failed;
+                }
+            },
+            None => None,
+        };
+        // tells an exit code of the runner from one of the sdk
+        let report_pipe = match ChildPipe::new() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                error!("Failed to create report pipe: {}", e);
+                return ExecStatus::Failed;
+            }
+        };
+        let child = match child_test.spawn_child(stderr_pipe.as_ref(), Some(&report_pipe), true) {
+            Ok(child) => child,
+            Err(e) => {
+                error!("Failed to start child process of test case {}: {}", child_test.name, e);
+                return ExecStatus::Failed;
+            }
+        };
+
+        let timeout = child_test.death_timeout();
+        match wait_child_with_report(child, Some(timeout), stderr_pipe, Some(report_pipe)) {
+            Ok(ChildExit { status: None, .. }) => {
+                error!(
+                    "Test case {} check panic failed! Child process timeout after {:?}.",
+                    child_test.name, timeout
+                );
+                ExecStatus::Failed
+            }
+            Ok(ChildExit { status: Some(status), cpu_time, .. }) if child_test.check_limit_hit(status, cpu_time) => {
+                ExecStatus::Failed
+            }
+            Ok(ChildExit { status: Some(status), output: stderr, report, .. }) => {
+                match child_test.check_death_status(status, report.finished) {
+                    ExecStatus::Passed => child_test.check_death_stderr(&stderr),
+                    res => res,
+                }
+            }
+            Err(e) => {
+                error!("Waitpid error: {}", e);
+                ExecStatus::Failed
+            }
+        }
+    }
+
+    // run the test in a child process, so a crash fails this test only
+    #[cfg(unix)]
+    fn run_isolated(&self) -> ExecStatus {
+        info!("start executing test case {} in isolated process.", self.name);
+        // an exit of the sdk before the cmds finished is not the test passing
+        let report_pipe = match ChildPipe::new() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                error!("Failed to create report pipe: {}", e);
+                return ExecStatus::Failed;
+            }
+        };
+        let child = match self.spawn_child(None, Some(&report_pipe), false) {
+            Ok(child) => child,
+            Err(e) => {
+                error!("Failed to start child process of test case {}: {}", self.name, e);
+                return ExecStatus::Failed;
+            }
+        };
+        let timeout = self.death_timeout();
+        match wait_child_with_report(child, Some(timeout), None, Some(report_pipe)) {
+            Ok(ChildExit { status: Some(status), cpu_time, .. }) if self.check_limit_hit(status, cpu_time) => {
+                ExecStatus::Failed
+            }
+            Ok(ChildExit { status: Some(WaitStatus::Signaled(_, signal, core_dumped)), .. }) => {
+                let core = if core_dumped { " (core dumped)" } else { "" };
+                error!("Test case {} failed! Isolated process crashed with signal {}{}.", self.name, signal, core);
+                ExecStatus::Failed
+            }
+            Ok(exit) => match ChildVerdict::from_exit(&exit) {
+                Ok(ChildVerdict::Passed) => ExecStatus::Passed,
+                Ok(ChildVerdict::Skipped) => ExecStatus::Skipped,
+                Ok(ChildVerdict::Failed) => ExecStatus::Failed,
+                Ok(ChildVerdict::Timeout) => {
+                    error!("Test case {} failed! Isolated process timeout after {:?}.", self.name, timeout);
+                    ExecStatus::Failed
+                }
+                Ok(ChildVerdict::Exited(code)) if !exit.report.finished => {
+                    error!("Test case {} failed! Isolated process exited with code {} before its cmds finished.", self.name, code);
+                    ExecStatus::Failed
+                }
+                Ok(verdict) => {
+                    error!("Test case {} failed! Isolated process {}.", self.name, verdict);
+                    ExecStatus::Failed
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    ExecStatus::Failed
+                }
+            },
+            Err(e) => {
+                error!("Waitpid error: {}", e);
+                ExecStatus::Failed
+            }
+        }
+    }
+
+    // limits of a test only make sense in a child, so they imply isolation
+    fn is_isolated(&self) -> bool {
+        self.isolate
+            .unwrap_or_else(|| self.limits.is_some() || ResourceEnv::settings().isolate)
+    }
+
+    // `expect_crash` leaves a crash of the child to the default action, not
+    // reported by the crash handler, `report_pipe` gets what the child reports
+    #[cfg(unix)]
+    fn spawn_child(
+        &self,
+        stderr_pipe: Option<&ChildPipe>,
+        report_pipe: Option<&ChildPipe>,
+        expect_crash: bool,
+    ) -> Result<Pid, Box<dyn std::error::Error>> {
+        match self.death_mode() {
+            DeathMode::Fork => self.fork_child(stderr_pipe, report_pipe, expect_crash),
+            DeathMode::Exec => self.exec_child(stderr_pipe, report_pipe, expect_crash),
+        }
+    }
+
+    // fork the running process, the child runs the test and never returns
+    #[cfg(unix)]
+    fn fork_child(
+        &self,
+        stderr_pipe: Option<&ChildPipe>,
+        report_pipe: Option<&ChildPipe>,
+        expect_crash: bool,
+    ) -> Result<Pid, Box<dyn std::error::Error>> {
+        match unsafe { fork() }? {
+            ForkResult::Child => {
+                if let Some(pipe) = stderr_pipe {
+                    pipe.redirect_stderr();
+                }
+                if let Some(pipe) = report_pipe {
+                    pipe.set_as_report();
+                }
+                if expect_crash {
+                    crash::reset_crash_handler();
+                }
+                let res_env = ResourceEnv::get_instance().map(|instance| instance.read().unwrap());
+                let (process_env, thread_env) = match res_env {
+                    Some(ref res_env) => (res_env.process_env.as_ref(), res_env.thread_env.as_ref()),
+                    None => (None, None),
+                };
+                if let Some(process_env) = process_env {
                     process_env.apply_env_init();
                 }
-                if let Some(thread_env) = &res_env.thread_env {
+                if let Some(thread_env) = thread_env {
                     thread_env.apply_env_init();
                 }
+                let mut child_test = self.clone();
                 child_test.thread_num = 1;
                 child_test.should_panic = false;
+                child_test.sync = None;
+                let code = child_test.run_in_child(&self.limits());
 
-                if let Some(thread_env) = &res_env.thread_env {
+                if let Some(thread_env) = thread_env {
                     thread_env.apply_env_exit();
                 }
-                if let Some(process_env) = &res_env.process_env {
+                if let Some(process_env) = process_env {
                     process_env.apply_env_exit();
                 }
-                let res = child_test.run_one_thread();
+                exit(code);
+            }
+            ForkResult::Parent { child } => Ok(child),
+        }
+    }
+
+    // start a fresh hitest process and send this test to it through stdin
+    #[cfg(unix)]
+    fn exec_child(
+        &self,
+        stderr_pipe: Option<&ChildPipe>,
+        report_pipe: Option<&ChildPipe>,
+        expect_crash: bool,
+    ) -> Result<Pid, Box<dyn std::error::Error>> {
+        let settings = ResourceEnv::settings();
+        let (process_env, thread_env) = match ResourceEnv::get_instance() {
+            Some(instance) => {
+                let res_env = instance.read().unwrap();
+                (res_env.process_env.clone(), res_env.thread_env.clone())
+            }
+            None => (None, None),
+        };
+        let mut test = self.clone();
+        test.thread_num = 1;
+        test.should_panic = false;
+
+        // the same log level and vars, so the stderr is the same as in fork mode
+        let log_lvl = match log::max_level() {
+            log::LevelFilter::Off | log::LevelFilter::Error => "error",
+            log::LevelFilter::Warn => "warn",
+            log::LevelFilter::Info => "info",
+            log::LevelFilter::Debug | log::LevelFilter::Trace => "debug",
+        };
+        let mut command = Command::new(std::env::current_exe()?);
+        command
+            .arg("--input")
+            .arg(&settings.libs_cfg)
+            .arg("--log")
+            .arg(log_lvl)
+            .args(settings.vars.args())
+            .arg("--death-case")
+            .stdin(Stdio::piped());
+        if let Some(pipe) = stderr_pipe {
+            command.stderr(pipe.stdio()?);
+        }
+        let case = DeathCase {
+            test,
+            limits: self.limits(),
+            process_env,
+            thread_env,
+            expect_crash,
+            report_fd: report_pipe.map(|pipe| pipe.pass_to(&mut command)),
+        };
+        let input = toml::to_string(&case)?;
+        let mut child = command.spawn()?;
+        // close our copy of the stderr pipe before waiting on it
+        drop(command);
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Err(e) = stdin.write_all(input.as_bytes()) {
+                warn!("Failed to send test case {} to child process: {}", self.name, e);
+            }
+        }
+        Ok(Pid::from_raw(child.id() as i32))
+    }
+
+    // the test body of a child process, returns its exit code
+    #[cfg(unix)]
+    fn run_in_child(&self, limits: &ResourceLimits) -> i32 {
+        limits.apply();
+        // must not unwind back into the runner of the parent
+        let (res, failed) = panic::catch_unwind(|| self.run_one_thread()).unwrap_or((ExecStatus::Failed, None));
+        child::report_finished();
+        // only the errno of the failed call tells it ran out of a resource
+        if let Some(limit) = failed.and_then(|failed| failed.errno).and_then(|errno| limits.exhausted(errno)) {
+            error!("Test case {} hit the {}.", self.name, limit);
+            return EXIT_CODE_LIMIT;
+        }
+        Self::exit_code(res)
+    }
+
+    fn limits(&self) -> ResourceLimits {
+        let default = ResourceEnv::settings().limits;
+        match self.limits {
+            Some(ref limits) => limits.or(&default),
+            None => default,
+        }
+    }
+
+    // report a child stopped by its resource limits, distinct from a crash
+    #[cfg(unix)]
+    fn check_limit_hit(&self, status: WaitStatus, cpu_time: Duration) -> bool {
+        let limits = self.limits();
+        match status {
+            WaitStatus::Exited(_, EXIT_CODE_LIMIT) if self.expect_exit_code != Some(EXIT_CODE_LIMIT) => {
+                error!("Test case {} failed! Child process ran out of resource limits {}.", self.name, limits);
+                true
+            }
+            WaitStatus::Signaled(_, signal, _) if limits.cpu_exceeded(status, cpu_time) => {
+                let expected = self.expect_signal.as_deref().and_then(Self::parse_signal);
+                if expected == Some(signal) {
+                    return false;
+                }
+                error!(
+                    "Test case {} failed! Child process exceeded the cpu time limit of {} s, killed by {}.",
+                    self.name,
+                    limits.cpu_s.unwrap_or_default(),
+                    signal
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn death_mode(&self) -> DeathMode {
+        self.death_mode
+            .unwrap_or_else(|| ResourceEnv::settings().death_mode)
+    }
+
+    #[cfg(unix)]
+    fn exit_code(status: ExecStatus) -> i32 {
+        match status {
+            ExecStatus::Passed => EXIT_CODE_PASSED,
+            ExecStatus::Failed => EXIT_CODE_FAILED,
+            ExecStatus::Skipped => EXIT_CODE_SKIPPED,
+        }
+    }
+
+    fn death_timeout(&self) -> Duration {
+        let timeout = match self.death_timeout_ms {
+            Some(ms) => Duration::from_millis(ms),
+            None => ResourceEnv::settings().death_timeout,
+        };
+        // leave the cpu time limit to end the child first, it tells more
+        match self.limits().cpu_s {
+            Some(cpu_s) => timeout.max(Duration::from_secs(cpu_s + 2)),
+            None => timeout,
+        }
+    }
 
-                let exit_code = match res {
-                    ExecStatus::Passed => EXIT_CODE_PASSED,
-                    ExecStatus::Failed => EXIT_CODE_FAILED,
-                    ExecStatus::Skipped => EXIT_CODE_SKIPPED,
+    // match the stderr of a death test child with the expected regex
+    #[cfg(unix)]
+    fn check_death_stderr(&self, stderr: &str) -> ExecStatus {
+        let Some(ref pattern) = self.expect_stderr else {
+            return ExecStatus::Passed;
+        };
+        match Regex::new(pattern) {
+            Ok(re) if re.is_match(stderr) => {
+                info!("Test case {} stderr matches '{}'.", self.name, pattern);
+                ExecStatus::Passed
+            }
+            Ok(_) => {
+                error!(
+                    "Test case {} check panic failed! stderr does not match '{}', stderr:\n{}",
+                    self.name, pattern, stderr
+                );
+                ExecStatus::Failed
+            }
+            Err(e) => {
+                error!("Test case {} has invalid expect_stderr: {}", self.name, e);
+                ExecStatus::Failed
+            }
+        }
+    }
+
+    fn is_death_test(&self) -> bool {
+        self.should_panic
+            || self.expect_signal.is_some()
+            || self.expect_exit_code.is_some()
+            || self.expect_stderr.is_some()
+    }
+
+    #[cfg(unix)]
+    fn parse_signal(name: &str) -> Option<Signal> {
+        let name = name.trim().to_uppercase();
+        if name.starts_with("SIG") {
+            name.parse().ok()
+        } else {
+            format!("SIG{}", name).parse().ok()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), TestError> {
+        let expects = std::iter::once((&self.expect_signal, &self.expect_exit_code)).chain(
+            self.inputs
+                .iter()
+                .map(|input| (&input.expect_signal, &input.expect_exit_code)),
+        );
+        if let Some(ref pattern) = self.expect_stderr {
+            Regex::new(pattern)
+                .map_err(|e| TestError::InvalidStderr(self.name.clone(), e.to_string()))?;
+        }
+        if let Some(ref duration) = self.duration {
+            parse_duration(duration).map_err(|e| TestError::InvalidDuration(self.name.clone(), e))?;
+        }
+        if let Some(ref bench) = self.bench {
+            bench.validate().map_err(|e| TestError::InvalidBench(self.name.clone(), e))?;
+        }
+        for input in &self.inputs {
+            input.validate().map_err(|e| TestError::InvalidInput(self.name.clone(), e))?;
+        }
+        for (signal, exit_code) in expects {
+            if signal.is_some() && exit_code.is_some() {
+                return Err(TestError::ConflictExpect(self.name.clone()));
+            }
+            #[cfg(unix)]
+            if let Some(signal) = signal {
+                if Self::parse_signal(signal).is_none() {
+                    return Err(TestError::InvalidSignal(signal.clone(), self.name.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // match the status of a death test child with the expected signal or exit code
+    #[cfg(unix)]
+    // `finished` tells the child ran all its cmds, so it exited with the
+    // exit code of the runner, not one of the sdk
+    fn check_death_status(&self, status: WaitStatus, finished: bool) -> ExecStatus {
+        match status {
+            WaitStatus::Exited(_, code) if finished => {
+                if code == EXIT_CODE_SKIPPED {
+                    info!("Test case {} skipped during panic check.", self.name);
+                    return ExecStatus::Skipped;
+                }
+                let result = match code {
+                    EXIT_CODE_PASSED => "passed",
+                    EXIT_CODE_FAILED => "failed",
+                    _ => "ended",
                 };
-                exit(exit_code);
-            }
-            Ok(ForkResult::Parent { child }) => {
-                let timeout = std::time::Duration::from_secs(1);
-                let start = std::time::Instant::now();
-
-                loop {
-                    match waitpid(child, Some(nix::sys::wait::WaitPidFlag::WNOHANG)) {
-                        Ok(WaitStatus::StillAlive) => {
-                            if start.elapsed() > timeout {
-                                let _ = nix::sys::signal::kill(child, nix::sys::signal::SIGKILL);
-                                error!(
-                                    "Test case {} check panic failed! Child process timeout.",
-                                    child_test.name
-                                );
-                                return ExecStatus::Failed;
-                            }
-                            std::thread::sleep(std::time::Duration::from_millis(100));
-                            continue;
-                        }
-                        Ok(status) => match status {
-                            WaitStatus::Exited(_, code) => {
-                                match code {
-                                    EXIT_CODE_SKIPPED => {
-                                        info!("Test case {} skipped during panic check.", child_test.name);
-                                        return ExecStatus::Skipped;
-                                    }
-                                    _ => {
-                                        error!("Test case {} check panic failed! Exited with code {}.", child_test.name, code);
-                                        return ExecStatus::Failed;
-                                    }
-                                }
-                            }
-                            WaitStatus::Signaled(_, signal, _) => {
-                                info!("Test case {} check panic successfully! crashed with signal {:#?}.", child_test.name, signal);
-                                return ExecStatus::Passed;
-                            }
-                            _ => {
-                                error!("Unexpected child status: {:?}", status);
-                                return ExecStatus::Failed;
-                            }
-                        },
-                        Err(e) => {
-                            error!("Waitpid error: {}", e);
-                            return ExecStatus::Failed;
-                        }
+                match (&self.expect_signal, self.expect_exit_code) {
+                    (_, Some(expected)) => error!("Test case {} check panic failed! expect exit code {}, but the cmds all returned and the test {}.", self.name, expected, result),
+                    (Some(signal), None) => error!("Test case {} check panic failed! expect signal {}, but the cmds all returned and the test {}.", self.name, signal, result),
+                    (None, None) => error!("Test case {} check panic failed! The cmds all returned and the test {}.", self.name, result),
+                }
+                ExecStatus::Failed
+            }
+            WaitStatus::Exited(_, code) => {
+                if self.expect_exit_code == Some(code) {
+                    info!("Test case {} check panic successfully! exited with expected code {}.", self.name, code);
+                    return ExecStatus::Passed;
+                }
+                match (&self.expect_signal, self.expect_exit_code) {
+                    (_, Some(expected)) => error!("Test case {} check panic failed! expect exit code {}, but exited with code {}.", self.name, expected, code),
+                    (Some(signal), None) => error!("Test case {} check panic failed! expect signal {}, but exited with code {}.", self.name, signal, code),
+                    (None, None) => error!("Test case {} check panic failed! Exited with code {}.", self.name, code),
+                }
+                ExecStatus::Failed
+            }
+            WaitStatus::Signaled(_, signal, core_dumped) => {
+                let core = if core_dumped { " (core dumped)" } else { "" };
+                if let Some(expected) = self.expect_exit_code {
+                    error!("Test case {} check panic failed! expect exit code {}, but crashed with signal {}{}.", self.name, expected, signal, core);
+                    return ExecStatus::Failed;
+                }
+                if let Some(ref name) = self.expect_signal {
+                    if Self::parse_signal(name) != Some(signal) {
+                        error!("Test case {} check panic failed! expect signal {}, but crashed with signal {}{}.", self.name, name, signal, core);
+                        return ExecStatus::Failed;
                     }
                 }
+                info!("Test case {} check panic successfully! crashed with signal {}{}.", self.name, signal, core);
+                ExecStatus::Passed
             }
-            Err(e) => {
-                error!("Fork failed: {}", e);
-                return ExecStatus::Failed;
+            _ => {
+                error!("Unexpected child status: {:?}", status);
+                ExecStatus::Failed
             }
         }
     }
@@ -158,7 +808,14 @@ impl Test {
         };
     }
 
-    fn run_one_thread(&self) -> ExecStatus {
+    // the status of the test and how its first failed cmd ran
+    fn run_one_thread(&self) -> (ExecStatus, Option<CmdRet>) {
+        let res = self.run_cmds();
+        crash::clear_context();
+        res
+    }
+
+    fn run_cmds(&self) -> (ExecStatus, Option<CmdRet>) {
         let mut cmds: Vec<Cmd> = self.cmds.clone();
         let is_main_thread = ThreadInfo::get_instance().lock().unwrap().is_main_thread();
 
@@ -168,20 +825,32 @@ impl Test {
 
         info!("start executing test case {}.", self.name);
         let mut final_status = ExecStatus::Passed;
+        let mut failed = None;
         for cmd in cmds {
-            match cmd.run() {
-                Ok(status) => {
-                    match status {
+            if cmd.is_barrier() {
+                if let Some(ref sync) = self.sync {
+                    debug!("Test case {} waits at barrier.", self.name);
+                    sync.wait();
+                }
+                continue;
+            }
+            crash::set_context(&self.name, &cmd);
+            match cmd.run_with_ret() {
+                Ok(cmd_ret) => {
+                    match cmd_ret.status {
                         ExecStatus::Failed => {
+                            #[cfg(unix)]
+                            child::report_check_failed(&cmd.opfunc, cmd_ret.ret);
+                            failed = failed.or(Some(cmd_ret));
                             if self.break_if_fail {
                                 debug!("Test case {} stopped because cmd {} failed!", self.name, &cmd.opfunc);
-                                return ExecStatus::Failed
+                                return (ExecStatus::Failed, failed)
                             }
                             final_status = ExecStatus::Failed;
                         }
                         ExecStatus::Skipped => {
                             debug!("Test case {} skipped by cmd {} pre-check.", self.name, &cmd.opfunc);
-                            return ExecStatus::Skipped;
+                            return (ExecStatus::Skipped, None);
                         }
                         ExecStatus::Passed => {
                             // continue
@@ -200,16 +869,16 @@ impl Test {
             error!("Test case {} execute failed!\n", self.name);
         }
 
-        final_status
+        (final_status, failed)
     }
 
-    fn process_input_group(&self) -> Vec<Test> {
-        if self.inputs.is_empty() {
-            return vec![self.clone()];
-        }
-
+    fn expand_inputs(&self) -> Vec<InputGroup> {
         let mut expanded_inputs = Vec::new();
         for input in &self.inputs {
+            if input.expand != Expand::Product || !input.zip.is_empty() {
+                expanded_inputs.extend(Self::expand_dims(input));
+                continue;
+            }
             let mut expanded = Self::expand_input_args(input);
 
             expanded = expanded
@@ -232,15 +901,74 @@ impl Test {
 
             expanded_inputs.extend(expanded);
         }
-
         expanded_inputs
+    }
+
+    /// Narrow this test down to what `filter` selects, matching both the test
+    /// name and the names of the sub tests expanded from its inputs.
+    pub fn select(&self, filter: &NameFilter) -> Option<Test> {
+        if filter.is_empty() {
+            return Some(self.clone());
+        }
+        if self.inputs.is_empty() {
+            return filter.matches(&[&self.name]).then(|| self.clone());
+        }
+        if filter.is_skipped(&[&self.name]) {
+            return None;
+        }
+
+        let inputs: Vec<InputGroup> = self
+            .expand_inputs()
+            .into_iter()
+            .filter(|input| {
+                let name = format!("{}_{}", self.name, input.name);
+                filter.is_filtered(&[&self.name, &name]) && !filter.is_skipped(&[&name])
+            })
+            .collect();
+        if inputs.is_empty() {
+            return None;
+        }
+        let mut test = self.clone();
+        test.inputs = inputs;
+        Some(test)
+    }
+
+    /// Expand the inputs of this test, keeping the sub tests selected by `tag_filter`.
+    pub fn expand(&self, tag_filter: &TagFilter) -> Vec<Test> {
+        self.process_input_group()
+            .into_iter()
+            .filter(|test| {
+                let selected = tag_filter.matches(&test.tags);
+                if !selected {
+                    debug!("Test case {} deselected by tags {:?}.", test.name, test.tags);
+                }
+                selected
+            })
+            .collect()
+    }
+
+    fn process_input_group(&self) -> Vec<Test> {
+        if self.inputs.is_empty() {
+            return vec![self.clone()];
+        }
+
+        self.expand_inputs()
             .into_iter()
             .map(|input| {
                 let mut test = self.clone();
                 test.inputs = vec![];
                 test.break_if_fail = input.break_if_fail.unwrap_or(self.break_if_fail);
                 test.should_panic = input.should_panic.unwrap_or(self.should_panic);
+                if input.expect_signal.is_some() || input.expect_exit_code.is_some() {
+                    test.expect_signal = input.expect_signal.clone();
+                    test.expect_exit_code = input.expect_exit_code;
+                }
                 test.name = format!("{}_{}", self.name, input.name);
+                for tag in &input.tags {
+                    if !test.tags.contains(tag) {
+                        test.tags.push(tag.clone());
+                    }
+                }
 
                 let resolved_args: HashMap<String, ArgValue> = input
                     .args
@@ -249,41 +977,41 @@ impl Test {
                     .map(|(k, v)| (k.clone(), v.clone()))
                     .collect();
 
-                test.cmds = test
-                    .cmds
-                    .iter()
-                    .map(|cmd| {
-                        let condition = match &cmd.condition {
-                            Condition::Eq(s) => {
-                                let replaced = replace_vars(s.clone(), &resolved_args);
-                                if replaced.starts_with("!") {
-                                    Condition::Ne(replaced[1..].to_string())
-                                } else {
-                                    Condition::Eq(replaced)
-                                }
-                            }
-                            Condition::Ne(s) => {
-                                Condition::Ne(replace_vars(s.clone(), &resolved_args))
-                            }
-                        };
-
-                        Cmd {
-                            opfunc: cmd.opfunc.clone(),
-                            condition,
-                            args: cmd
-                                .args
-                                .iter()
-                                .map(|arg| replace_vars(arg.clone(), &resolved_args))
-                                .collect(),
-                            perf: cmd.perf,
-                        }
-                    })
-                    .collect();
+                test.cmds = test.cmds.iter().map(|cmd| replace_cmd_vars(cmd, &resolved_args)).collect();
                 test
             })
             .collect()
     }
 
+    // one input per row of a covering array of the dimensions, the full
+    // product unless n-wise, named by the args in sorted order so the names
+    // are the same in every run
+    fn expand_dims(input: &InputGroup) -> Vec<InputGroup> {
+        let dims = input.dims();
+        let sizes: Vec<usize> = dims.iter().map(|dim| dim.len()).collect();
+        let strength = match input.expand {
+            Expand::NWise(strength) => strength,
+            _ => sizes.len(),
+        };
+        covering_array(&sizes, strength)
+            .into_iter()
+            .map(|row| {
+                let mut new_input = input.clone();
+                new_input.expand = Expand::Product;
+                new_input.zip = Vec::new();
+                for (dim, i) in dims.iter().zip(row) {
+                    for (key, value) in &dim[i] {
+                        new_input
+                            .args
+                            .insert(key.clone(), ArgValue::Single(value.clone()));
+                        new_input.name = format!("{}_{}={}", new_input.name, key, value);
+                    }
+                }
+                new_input
+            })
+            .collect()
+    }
+
     fn expand_input_args(input: &InputGroup) -> Vec<InputGroup> {
         let mut expanded = Vec::new();
         let current = input.clone();
@@ -303,15 +1031,7 @@ impl Test {
                     return expanded;
                 }
                 ArgValue::Range(range) => {
-                    let step = range.step.unwrap_or(1);
-                    let mut values = Vec::new();
-                    let mut i = range.start;
-                    while i <= range.end {
-                        values.push(i.to_string());
-                        i += step;
-                    }
-
-                    for (_, val) in values.iter().enumerate() {
+                    for (_, val) in range.values().iter().enumerate() {
                         let mut new_input = current.clone();
                         new_input
                             .args
@@ -328,9 +1048,16 @@ impl Test {
     }
 
     fn execute(&self) -> ExecStatus {
+        // a child process cannot reach the barriers of this process, the
+        // parent waits at the start barrier for it
+        if self.is_death_test() || self.is_isolated() {
+            if let Some(ref sync) = self.sync {
+                sync.wait();
+            }
+        }
         // std::panic not send to other thread
         let result = panic::catch_unwind(|| {
-            if self.should_panic {
+            if self.is_death_test() {
                 #[cfg(unix)]
                 {
                     let mut child_test = self.clone();
@@ -342,8 +1069,18 @@ impl Test {
                     error!("panic check is not supported on this platform.");
                     ExecStatus::Failed
                 }
+            } else if self.is_isolated() {
+                #[cfg(unix)]
+                {
+                    self.run_isolated()
+                }
+                #[cfg(not(unix))]
+                {
+                    error!("isolation is not supported on this platform.");
+                    ExecStatus::Failed
+                }
             } else {
-                self.run_one_thread()
+                self.run_one_thread().0
             }
         });
 
@@ -355,77 +1092,248 @@ impl Test {
             }
         }
     }
+    // re-execute a failed test up to `retries` times, returns the final status
+    // and whether it took more than one attempt.
+    fn execute_with_retries(&self, retries: usize) -> (ExecStatus, bool) {
+        let mut status = self.execute();
+        // the other threads do not wait for the retries
+        self.leave_sync(1);
+        let mut attempt = 0;
+        while status == ExecStatus::Failed && attempt < retries && !ResourceEnv::should_stop() {
+            attempt += 1;
+            warn!(
+                "Test case {} failed, retry {}/{}.",
+                self.name, attempt, retries
+            );
+            status = Test { sync: None, ..self.clone() }.execute();
+        }
+        if attempt > 0 && status == ExecStatus::Passed {
+            warn!(
+                "Test case {} passed after {} retries, it may be flaky!",
+                self.name, attempt
+            );
+        }
+        (status, attempt > 0)
+    }
+
+    // stop taking part in the sync point for `count` threads of this test
+    fn leave_sync(&self, count: usize) {
+        if let Some(ref sync) = self.sync {
+            for _ in 0..count {
+                sync.leave();
+            }
+        }
+    }
+
+    /// Report this test as not run, used when the run stops early.
+    pub fn not_run(&self, tag_filter: &TagFilter) -> TestResult {
+        let mut res = TestResult::default();
+        for test in self.expand(tag_filter) {
+            res.add_not_run(test.name, self.thread_num.max(1) as usize);
+        }
+        res
+    }
+
+    /// Number of test cases this test runs, after expanding inputs and threads.
+    pub fn case_count(&self, tag_filter: &TagFilter) -> usize {
+        self.expand(tag_filter).len() * self.thread_num.max(1) as usize
+    }
+
+    /// Run the test, again and again until its duration expires when one is given.
     pub fn run(&self) -> TestResult {
+        if let Some(ref bench) = self.bench {
+            return self.run_bench(bench);
+        }
+        match self.duration.as_deref().map(parse_duration) {
+            Some(Ok(duration)) if self.disabled.is_none() => {
+                let interval = ResourceEnv::settings().soak_interval;
+                run_soak(&self.name, duration, interval, || self.run_once()).aggregate()
+            }
+            _ => self.run_once(),
+        }
+    }
+
+    // benchmark every sub test expanded from the inputs
+    fn run_bench(&self, bench: &BenchConfig) -> TestResult {
+        let settings = ResourceEnv::settings();
+        if let Some(ref reason) = self.disabled {
+            warn!("Benchmark {} is disabled: {}\n", self.name, reason);
+            return TestResult {
+                disabled: bench.threads.len(),
+                ..Default::default()
+            };
+        }
+        let mut result = TestResult::default();
+        for test in self.expand(&settings.tag_filter) {
+            result.merge(&run_bench(&test, bench));
+        }
+        result
+    }
+
+    fn run_once(&self) -> TestResult {
+        let process_num = self.process_num.unwrap_or(1);
+        if process_num > 1 && self.disabled.is_none() && !ResourceEnv::should_stop() {
+            #[cfg(unix)]
+            {
+                let expected = self.case_count(&ResourceEnv::settings().tag_filter);
+                // the workers synchronize their own threads only
+                self.leave_sync(expected);
+                let test = Test { sync: None, ..self.clone() };
+                return super::process::run_in_processes(&self.name, process_num, expected, || {
+                    test.run_in_process()
+                });
+            }
+            #[cfg(not(unix))]
+            warn!("process_num of test {} is not supported on this platform, run in one process.", self.name);
+        }
+        self.run_in_process()
+    }
+
+    fn run_in_process(&self) -> TestResult {
         debug!(
             "start executing test case {}, inputs: {:?}.",
             &self.name, &self.inputs
         );
-        let tests = self.process_input_group();
-        let tests: Vec<_> = tests
+        let settings = ResourceEnv::settings();
+        if ResourceEnv::should_stop() {
+            let res = self.not_run(&settings.tag_filter);
+            self.leave_sync(res.not_run);
+            return res;
+        }
+        let tests = self.expand(&settings.tag_filter);
+        if tests.is_empty() {
+            debug!("Test {} has no sub test selected, do nothing.", self.name);
+            return TestResult::default();
+        }
+        let mut tests: Vec<_> = tests
             .into_iter()
             .flat_map(|test| (0..self.thread_num).map(move |_| test.clone()))
             .collect();
 
-        let serial = self.serial.unwrap_or(false);
+        // all threads of the test run at once to meet at the barriers
+        let sync = match self.sync {
+            Some(ref sync) => Some(sync.clone()),
+            None if self.cmds.iter().any(Cmd::is_barrier) => {
+                Some(Arc::new(SyncPoint::new(tests.len())))
+            }
+            None => None,
+        };
+        if let Some(ref sync) = sync {
+            for test in tests.iter_mut() {
+                test.sync = Some(sync.clone());
+            }
+        }
+
+        if let Some(ref reason) = self.disabled {
+            warn!(
+                "Test {} with {} sub tests is disabled: {}\n",
+                self.name,
+                tests.len(),
+                reason
+            );
+            return TestResult {
+                disabled: tests.len(),
+                ..Default::default()
+            };
+        }
 
-        let aggregate_results = |results: Vec<ExecStatus>| -> TestResult {
+        let serial = self.serial.unwrap_or(false) && sync.is_none();
+        // an expected failure is not worth retrying
+        let retries = match self.xfail {
+            Some(_) => 0,
+            None => self.retries.unwrap_or(settings.retries),
+        };
+
+        let exec = |test: &Test| -> Outcome {
+            if ResourceEnv::should_stop() {
+                test.leave_sync(1);
+                return Outcome::NotRun(test.name.clone());
+            }
+            let (status, retried) = test.execute_with_retries(retries);
+            if status == ExecStatus::Failed && self.xfail.is_none() {
+                ResourceEnv::record_failure();
+            }
+            Outcome::Done(status, retried)
+        };
+
+        let aggregate_results = |results: Vec<Outcome>| -> TestResult {
             let mut res = TestResult::default();
-            for status in results {
-                match status {
-                    ExecStatus::Passed => res.passed += 1,
-                    ExecStatus::Failed => res.failed += 1,
-                    ExecStatus::Skipped => res.skipped += 1,
+            for outcome in results {
+                let (status, retried) = match outcome {
+                    Outcome::Done(status, retried) => (status, retried),
+                    Outcome::NotRun(name) => {
+                        res.add_not_run(name, 1);
+                        continue;
+                    }
+                };
+                match (status, &self.xfail) {
+                    (ExecStatus::Passed, None) => {
+                        res.passed += 1;
+                        if retried {
+                            res.flaky += 1;
+                        }
+                    }
+                    (ExecStatus::Failed, None) => res.failed += 1,
+                    (ExecStatus::Passed, Some(_)) => res.xpass += 1,
+                    (ExecStatus::Failed, Some(_)) => res.xfail += 1,
+                    (ExecStatus::Skipped, _) => res.skipped += 1,
                 }
             }
             res
         };
 
-        let results: Vec<ExecStatus> = if serial {
+        let results: Vec<Outcome> = if sync.is_some() {
+            info!("Run test {} with {} sub tests on their own threads!", self.name, tests.len());
+            executor::spawn_map(&tests, exec)
+        } else if serial {
             info!("Run test {} with {} sub tests serially!", self.name, tests.len());
-            tests.into_iter().map(|test| test.execute()).collect()
+            let mut tests = tests;
+            if settings.shuffle {
+                tests.shuffle(&mut settings.rng(&self.name));
+            }
+            tests.iter().map(exec).collect()
         } else {
             info!("Run test {} with {} sub tests parallelly!", self.name, tests.len());
-            let max_thread = {
-                let res_env = ResourceEnv::get_instance().unwrap().read().unwrap();
-                res_env.max_threads
-            };
-            let results: Vec<ExecStatus> = if let Some(max_thread) = max_thread {
+            let results: Vec<Outcome> = if let Some(max_thread) = settings.max_threads {
                 if max_thread < tests.len() {
                     warn!("test case {} total sub test cases is {}, but max-threads is {} thread, will be grouped.",
                     self.name, tests.len(), max_thread);
 
-                    let mut rng = thread_rng();
+                    let mut rng = settings.rng(&self.name);
                     let mut shuffled_tests = tests;
                     shuffled_tests.shuffle(&mut rng);
 
                     let mut results = Vec::new();
                     for chunk in shuffled_tests.chunks(max_thread) {
-                        let chunk_results: Vec<_> =
-                            chunk.into_par_iter().map(|test| test.execute()).collect();
-                        results.extend(chunk_results);
+                        results.extend(executor::par_map(chunk, exec));
                     }
                     results
                 } else {
-                    tests.into_par_iter().map(|test| test.execute()).collect()
+                    executor::par_map(&tests, exec)
                 }
             } else {
-                tests.into_par_iter().map(|test| test.execute()).collect()
+                executor::par_map(&tests, exec)
             };
             results
         };
 
         let test_result = aggregate_results(results);
 
+        if let Some(ref bug) = self.xfail {
+            if test_result.xpass > 0 {
+                warn!(
+                    "Test {} is expected to fail by {}, but {} sub tests passed unexpectedly, consider re-enabling it!",
+                    self.name, bug, test_result.xpass
+                );
+            } else {
+                info!("Test {} failed as expected by {}.", self.name, bug);
+            }
+        }
+
         if test_result.failed > 0 {
-            error!(
-                "Test {} execute finished! Passed: {}, Failed: {}, Skipped: {}\n",
-                self.name, test_result.passed, test_result.failed, test_result.skipped
-            );
+            error!("Test {} execute finished! {}\n", self.name, test_result);
         } else {
-             info!(
-                "Test {} execute finished! Passed: {}, Failed: {}, Skipped: {}\n",
-                self.name, test_result.passed, test_result.failed, test_result.skipped
-            );
+            info!("Test {} execute finished! {}\n", self.name, test_result);
         }
 
         test_result
@@ -493,7 +1401,22 @@ impl fmt::Display for Test {
         writeln!(f, "Test: {}", self.name)?;
         writeln!(f, "Threads: {}", self.thread_num)?;
         writeln!(f, "Should Panic: {}", self.should_panic)?;
+        if let Some(ref signal) = self.expect_signal {
+            writeln!(f, "Expect Signal: {}", signal)?;
+        }
+        if let Some(code) = self.expect_exit_code {
+            writeln!(f, "Expect Exit Code: {}", code)?;
+        }
         writeln!(f, "Break if Fail: {}", self.break_if_fail)?;
+        if !self.tags.is_empty() {
+            writeln!(f, "Tags: {:?}", self.tags)?;
+        }
+        if let Some(ref reason) = self.disabled {
+            writeln!(f, "Disabled: {}", reason)?;
+        }
+        if let Some(ref bug) = self.xfail {
+            writeln!(f, "Expected Failure: {}", bug)?;
+        }
 
         if !self.inputs.is_empty() {
             writeln!(f, "Input Groups:")?;
@@ -511,23 +1434,83 @@ impl fmt::Display for Test {
     }
 }
 
+/// The cmd with the `$key` of the single values replaced in its args and
+/// condition, a condition of `$!key` becomes expect_ne.
+pub fn replace_cmd_vars(cmd: &Cmd, vars: &HashMap<String, ArgValue>) -> Cmd {
+    let condition = match &cmd.condition {
+        Condition::Eq(s) => {
+            let replaced = replace_vars(s.clone(), vars);
+            match replaced.strip_prefix('!') {
+                Some(expect) => Condition::Ne(expect.to_string()),
+                None => Condition::Eq(replaced),
+            }
+        }
+        Condition::Ne(s) => Condition::Ne(replace_vars(s.clone(), vars)),
+    };
+
+    Cmd {
+        opfunc: cmd.opfunc.clone(),
+        condition,
+        args: cmd.args.iter().map(|arg| replace_vars(arg.clone(), vars)).collect(),
+        perf: cmd.perf,
+    }
+}
+
+/// Whether `key` can be referred to as `$key`, a name runs until the first
+/// char that is not alphanumeric or `_`.
+pub fn is_var_name(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// every `$name` or `$!name` in `s` as (range, negated, name), see `is_var_name`
+fn var_tokens(s: &str) -> Vec<(std::ops::Range<usize>, bool, &str)> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while let Some(pos) = s[i..].find('$') {
+        let start = i + pos;
+        let negated = s[start + 1..].starts_with('!');
+        let name_start = start + 1 + negated as usize;
+        let name_end = s[name_start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(s.len(), |len| name_start + len);
+        tokens.push((start..name_end, negated, &s[name_start..name_end]));
+        i = name_end.max(start + 1);
+    }
+    tokens
+}
+
+/// Whether `s` refers to the var `key` as `$key` or `$!key`, not as a
+/// longer name starting with it such as `$key2`.
+pub fn refers_var(s: &str, key: &str) -> bool {
+    var_tokens(s).iter().any(|(_, _, name)| *name == key)
+}
+
 fn replace_vars(s: String, vars: &HashMap<String, ArgValue>) -> String {
-    let mut result = s;
-    for (k, v) in vars {
-        let value = match v {
-            ArgValue::Single(s) => s.clone(),
-            ArgValue::List(_) => panic!("List values should be expanded before replace_vars"),
-            ArgValue::Range(_) => panic!("Range values should be expanded before replace_vars"),
+    let mut result = String::new();
+    let mut last = 0;
+    for (range, negated, name) in var_tokens(&s) {
+        let value = match vars.get(name) {
+            Some(ArgValue::Single(s)) => s,
+            Some(ArgValue::List(_)) => panic!("List values should be expanded before replace_vars"),
+            Some(ArgValue::Range(_)) => panic!("Range values should be expanded before replace_vars"),
+            None => continue,
         };
-        result = result.replace(&format!("${}", k), &value);
-        result = result.replace(&format!("$!{}", k), &format!("!{}", value));
+        result.push_str(&s[last..range.start]);
+        if negated {
+            result.push('!');
+        }
+        result.push_str(value);
+        last = range.end;
     }
+    result.push_str(&s[last..]);
     result
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    #[cfg(unix)]
+    use crate::child::wait_child;
     use crate::{input::RangeExpr, ArgValue};
 
     #[test]
@@ -546,6 +1529,18 @@ mod test {
         assert_eq!(result, "expect=!123");
     }
 
+    #[test]
+    fn test_replace_vars_whole_name() {
+        let mut vars = HashMap::new();
+        vars.insert("len".into(), ArgValue::Single("8".into()));
+        vars.insert("len2".into(), ArgValue::Single("16".into()));
+        let result = replace_vars("a=$len,b=$len2,c=$length,d=$!len$".into(), &vars);
+        assert_eq!(result, "a=8,b=16,c=$length,d=!8$");
+        assert!(refers_var("off=$len", "len"));
+        assert!(!refers_var("off=$len2", "len"));
+        assert!(!refers_var("off=len", "len"));
+    }
+
     #[test]
     fn test_process_input_groups() {
         let test = Test {
@@ -614,9 +1609,9 @@ mod test {
 
         let processed = test.process_input_group();
         assert_eq!(processed.len(), 3);
-        assert_eq!(processed[0].name, "list_test_list_input_0");
-        assert_eq!(processed[1].name, "list_test_list_input_1");
-        assert_eq!(processed[2].name, "list_test_list_input_2");
+        assert_eq!(processed[0].name, "list_test_list_input_val=a");
+        assert_eq!(processed[1].name, "list_test_list_input_val=b");
+        assert_eq!(processed[2].name, "list_test_list_input_val=c");
     }
 
     #[test]
@@ -637,9 +1632,10 @@ mod test {
                 args: [(
                     "val".to_string(),
                     ArgValue::Range(RangeExpr {
-                        start: 1,
-                        end: 3,
-                        step: Some(1),
+                        start: 1.into(),
+                        end: 3.into(),
+                        step: Some(1.into()),
+                        ..Default::default()
                     }),
                 )]
                 .iter()
@@ -652,9 +1648,72 @@ mod test {
 
         let processed = test.process_input_group();
         assert_eq!(processed.len(), 3);
-        assert_eq!(processed[0].name, "range_test_range_input_0");
-        assert_eq!(processed[1].name, "range_test_range_input_1");
-        assert_eq!(processed[2].name, "range_test_range_input_2");
+        assert_eq!(processed[0].name, "range_test_range_input_val=1");
+        assert_eq!(processed[1].name, "range_test_range_input_val=2");
+        assert_eq!(processed[2].name, "range_test_range_input_val=3");
+    }
+
+    #[test]
+    fn test_pairwise_input_expansion() {
+        let input: InputGroup = toml::from_str(
+            r#"
+            name = "pw"
+            expand = "pairwise"
+            args = { a = ["0", "1", "2"], b = ["x", "y", "z"], c = { start = 1, end = 3 }, d = "4" }
+            "#,
+        )
+        .unwrap();
+        let test = Test {
+            name: "t".to_string(),
+            cmds: vec![Cmd {
+                opfunc: "test_func".to_string(),
+                condition: Condition::Eq("0".to_string()),
+                args: vec!["a=$a".to_string(), "b=$b".to_string(), "c=$c".to_string(), "d=$d".to_string()],
+                perf: false,
+            }],
+            inputs: vec![input],
+            ..Default::default()
+        };
+
+        let processed = test.process_input_group();
+        // the full product has 27
+        assert_eq!(processed.len(), 10);
+        assert_eq!(processed[0].name, "t_pw_a=0_b=x_c=1");
+        assert_eq!(processed[0].cmds[0].args, ["a=0", "b=x", "c=1", "d=4"]);
+        let names: Vec<_> = processed.iter().map(|test| test.name.clone()).collect();
+        let again: Vec<_> = test.process_input_group().into_iter().map(|test| test.name).collect();
+        assert_eq!(names, again);
+    }
+
+    #[test]
+    fn test_zip_input_expansion() {
+        let input: InputGroup = toml::from_str(
+            r#"
+            name = "z"
+            zip = [["off", "expected"]]
+            args = { off = ["0", "4", "8"], expected = ["111", "222", "333"], len = ["1", "2"] }
+            "#,
+        )
+        .unwrap();
+        let test = Test {
+            name: "t".to_string(),
+            cmds: vec![Cmd {
+                opfunc: "test_func".to_string(),
+                condition: Condition::Eq("$expected".to_string()),
+                args: vec!["off=$off".to_string(), "len=$len".to_string()],
+                perf: false,
+            }],
+            inputs: vec![input],
+            ..Default::default()
+        };
+        assert!(test.validate().is_ok());
+
+        let processed = test.process_input_group();
+        assert_eq!(processed.len(), 6);
+        assert_eq!(processed[0].name, "t_z_len=1_off=0_expected=111");
+        assert_eq!(processed[4].name, "t_z_len=2_off=4_expected=222");
+        assert_eq!(processed[4].cmds[0].args, ["off=4", "len=2"]);
+        assert_eq!(processed[4].cmds[0].condition, Condition::Eq("222".to_string()));
     }
 
     #[test]
@@ -689,9 +1748,291 @@ mod test {
 
         let processed = test.process_input_group();
         assert_eq!(processed.len(), 2);
-        assert_eq!(processed[0].name, "multi_test_test_input_0");
-        assert_eq!(processed[1].name, "multi_test_test_input_1");
+        assert_eq!(processed[0].name, "multi_test_test_input_val=100");
+        assert_eq!(processed[1].name, "multi_test_test_input_val=200");
+    }
+
+    #[test]
+    fn test_input_tags_inherited() {
+        let test = Test {
+            name: "tag_test".to_string(),
+            tags: vec!["smoke".to_string()],
+            inputs: vec![
+                InputGroup {
+                    name: "plain".to_string(),
+                    ..Default::default()
+                },
+                InputGroup {
+                    name: "dma".to_string(),
+                    tags: vec!["dma".to_string(), "smoke".to_string()],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let processed = test.process_input_group();
+        assert_eq!(processed.len(), 2);
+        assert_eq!(processed[0].tags, vec!["smoke".to_string()]);
+        assert_eq!(
+            processed[1].tags,
+            vec!["smoke".to_string(), "dma".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_disabled_and_xfail() {
+        let test: Test = toml::from_str(
+            r#"
+            name = "known_bug"
+            disabled = "sdk crashes on close"
+            xfail = "bug-123"
+            cmds = []
+        "#,
+        )
+        .unwrap();
+        assert_eq!(test.disabled.as_deref(), Some("sdk crashes on close"));
+        assert_eq!(test.xfail.as_deref(), Some("bug-123"));
+
+        let mut res = TestResult {
+            passed: 1,
+            xfail: 2,
+            ..Default::default()
+        };
+        res.merge(&TestResult {
+            failed: 1,
+            xpass: 1,
+            disabled: 3,
+            ..Default::default()
+        });
+        assert_eq!(res.total(), 8);
+        assert_eq!(
+            res.to_string(),
+            "Passed: 1, Failed: 1, Skipped: 0, Disabled: 3, XFail: 2, XPass: 1"
+        );
     }
+
+    #[test]
+    fn test_flaky_result() {
+        let test: Test = toml::from_str(
+            r#"
+            name = "flaky"
+            retries = 3
+            cmds = []
+        "#,
+        )
+        .unwrap();
+        assert_eq!(test.retries, Some(3));
+
+        let res = TestResult {
+            passed: 4,
+            flaky: 1,
+            ..Default::default()
+        };
+        assert_eq!(res.total(), 4);
+        assert_eq!(
+            res.to_string(),
+            "Passed: 4, Failed: 0, Skipped: 0 (1 passed after retry)"
+        );
+    }
+
+    #[test]
+    fn test_not_run_result() {
+        let mut res = TestResult::default();
+        res.add_not_run("a".to_string(), 2);
+        let mut other = TestResult {
+            failed: 1,
+            ..Default::default()
+        };
+        other.add_not_run("a".to_string(), 1);
+        other.add_not_run("b".to_string(), 1);
+        res.merge(&other);
+        assert_eq!(res.not_run, 4);
+        assert_eq!(res.not_run_tests, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(res.total(), 5);
+        assert_eq!(res.to_string(), "Passed: 0, Failed: 1, Skipped: 0, Not Run: 4");
+    }
+
+    #[test]
+    fn test_aggregate_result() {
+        let soaked = TestResult {
+            passed: 25670,
+            flaky: 3,
+            ..Default::default()
+        };
+        let res = soaked.aggregate();
+        assert_eq!((res.total(), res.passed, res.flaky), (1, 1, 1));
+        let soaked = TestResult {
+            passed: 25670,
+            failed: 1,
+            ..Default::default()
+        };
+        assert_eq!(soaked.aggregate().to_string(), "Passed: 0, Failed: 1, Skipped: 0");
+        assert_eq!(TestResult::default().aggregate().total(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_death_status() {
+        use nix::unistd::Pid;
+        let pid = Pid::from_raw(1);
+        let mut test = Test {
+            name: "death".to_string(),
+            should_panic: true,
+            ..Default::default()
+        };
+        let segv = WaitStatus::Signaled(pid, Signal::SIGSEGV, true);
+        let abort = WaitStatus::Signaled(pid, Signal::SIGABRT, false);
+        let exit3 = WaitStatus::Exited(pid, 3);
+        assert_eq!(test.check_death_status(segv, false), ExecStatus::Passed);
+        assert_eq!(test.check_death_status(abort, false), ExecStatus::Passed);
+        assert_eq!(test.check_death_status(exit3, false), ExecStatus::Failed);
+
+        test.expect_signal = Some("segv".to_string());
+        assert!(test.validate().is_ok());
+        assert_eq!(test.check_death_status(segv, false), ExecStatus::Passed);
+        assert_eq!(test.check_death_status(abort, false), ExecStatus::Failed);
+
+        test.expect_signal = None;
+        test.expect_exit_code = Some(3);
+        assert_eq!(test.check_death_status(exit3, false), ExecStatus::Passed);
+        assert_eq!(test.check_death_status(segv, false), ExecStatus::Failed);
+
+        test.expect_signal = Some("SIGNOPE".to_string());
+        assert!(matches!(test.validate(), Err(TestError::ConflictExpect(_))));
+        test.expect_exit_code = None;
+        assert!(matches!(test.validate(), Err(TestError::InvalidSignal(_, _))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_death_exit_code_of_runner() {
+        // no lib is loaded, so the cmd fails and the child exits with code 1
+        let test = Test {
+            name: "exit1".to_string(),
+            cmds: vec![Cmd {
+                opfunc: "Call_read32".to_string(),
+                condition: Condition::Eq("0".to_string()),
+                args: vec!["addr_idx=1".to_string()],
+                perf: false,
+            }],
+            should_panic: true,
+            expect_exit_code: Some(1),
+            death_mode: Some(DeathMode::Fork),
+            ..Default::default()
+        };
+        assert_eq!(Test::check_panic(test.clone()), ExecStatus::Failed);
+        // exit(1) by the sdk before the cmds all returned
+        let exit1 = WaitStatus::Exited(Pid::from_raw(1), 1);
+        assert_eq!(test.check_death_status(exit1, false), ExecStatus::Passed);
+        assert_eq!(test.check_death_status(exit1, true), ExecStatus::Failed);
+        let passed = WaitStatus::Exited(Pid::from_raw(1), EXIT_CODE_PASSED);
+        let zero = Test {
+            expect_exit_code: Some(0),
+            ..test
+        };
+        assert_eq!(zero.check_death_status(passed, true), ExecStatus::Failed);
+        assert_eq!(zero.check_death_status(passed, false), ExecStatus::Passed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_death_case_round_trip() {
+        let test: Test = toml::from_str(
+            r#"
+            name = "exec_death"
+            expect_signal = "SIGABRT"
+            death_mode = "exec"
+            cmds = [
+                { opfunc = "Call_malloc", expect_eq = 0, args = ["len=8", "mem_idx=1"] },
+                { opfunc = "Call_free", expect_ne = "0x1", args = ["mem_idx=1"] },
+            ]
+            inputs = [{ name = "i", args = { len = ["8"] } }]
+            "#,
+        )
+        .unwrap();
+        let env = Env {
+            name: "env".to_string(),
+            init: vec![test.cmds[0].clone()],
+            exit: vec![],
+            tests: vec![],
+        };
+        let case = DeathCase {
+            test,
+            limits: ResourceLimits {
+                nofile: Some(64),
+                ..Default::default()
+            },
+            process_env: Some(env),
+            thread_env: None,
+            expect_crash: true,
+            report_fd: Some(3),
+        };
+        let case: DeathCase = toml::from_str(&toml::to_string(&case).unwrap()).unwrap();
+        assert_eq!(case.test.name, "exec_death");
+        assert_eq!(case.test.death_mode, Some(DeathMode::Exec));
+        assert_eq!(case.test.cmds[1].condition, Condition::Ne("0x1".to_string()));
+        assert!(case.test.inputs.is_empty());
+        assert_eq!(case.process_env.unwrap().init[0].args, ["len=8", "mem_idx=1"]);
+        assert!(case.thread_env.is_none());
+        assert_eq!(case.limits.nofile, Some(64));
+        assert!(case.expect_crash);
+        assert_eq!(case.report_fd, Some(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_child_verdict_early_exit() {
+        let exit = |code: i32, finished: bool| ChildExit {
+            status: Some(WaitStatus::Exited(Pid::from_raw(1), code)),
+            output: String::new(),
+            report: ChildReport {
+                finished,
+                ..Default::default()
+            },
+            cpu_time: Duration::ZERO,
+        };
+        assert_eq!(ChildVerdict::from_exit(&exit(EXIT_CODE_PASSED, true)), Ok(ChildVerdict::Passed));
+        assert_eq!(ChildVerdict::from_exit(&exit(EXIT_CODE_FAILED, true)), Ok(ChildVerdict::Failed));
+        // exit(0) in the middle of the cmds is a finding, not a pass
+        let early = ChildVerdict::from_exit(&exit(EXIT_CODE_PASSED, false)).unwrap();
+        assert_eq!(early, ChildVerdict::Exited(0));
+        assert!(early.is_failure());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_expected_crash_not_reported() {
+        crate::install_crash_handler();
+        // the child aborts when it copies the cmds under the address space limit
+        let test = Test {
+            name: "oom".to_string(),
+            cmds: vec![Cmd {
+                opfunc: "Call_noop".to_string(),
+                condition: Condition::Eq("0".to_string()),
+                args: vec!["x".repeat(64 << 20)],
+                perf: false,
+            }],
+            expect_signal: Some("SIGABRT".to_string()),
+            limits: Some(ResourceLimits {
+                as_mb: Some(1),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let crash = |expect_crash: bool| {
+            let pipe = ChildPipe::new().unwrap();
+            let child = test.fork_child(Some(&pipe), None, expect_crash).unwrap();
+            let exit = wait_child(child, Some(Duration::from_secs(10)), Some(pipe)).unwrap();
+            assert_eq!(test.check_death_status(exit.status.unwrap(), false), ExecStatus::Passed);
+            exit.output
+        };
+        let stderr = crash(true);
+        assert!(!stderr.contains("HiTest caught"), "{}", stderr);
+        // an unexpected crash is still reported
+        assert!(crash(false).contains("HiTest caught SIGABRT"));
+    }
+
     #[test]
     fn test_resolve_refs() {
         let mut shared_inputs = HashMap::new();
@@ -0,0 +1,270 @@
+use super::input::RangeExpr;
+use super::{is_var_name, refers_var, replace_cmd_vars, ArgValue, Condition, InputGroup, Test};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Values given by `--vars` files and `-D key=value` overriding the args of
+/// the test config, a later one wins over an earlier one and `-D` wins over
+/// the files.
+#[derive(Debug, Clone, Default)]
+pub struct Vars {
+    values: BTreeMap<String, ArgValue>,
+    // the options they were given by, in order
+    args: Vec<String>,
+}
+
+// a value of a vars file, numbers and bools are taken as their text
+fn arg_value(key: &str, value: toml::Value) -> Result<ArgValue, String> {
+    let text = |value: toml::Value| match value {
+        toml::Value::String(s) => Ok(s),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        other => Err(format!("invalid value of '{}': {}", key, other)),
+    };
+    match value {
+        toml::Value::Array(items) => Ok(ArgValue::List(items.into_iter().map(text).collect::<Result<_, _>>()?)),
+        toml::Value::Table(_) => value
+            .try_into::<RangeExpr>()
+            .map(ArgValue::Range)
+            .map_err(|e| format!("invalid range of '{}': {}", key, e)),
+        value => text(value).map(ArgValue::Single),
+    }
+}
+
+impl Vars {
+    /// Read the `files` in order, then the `defines` of the form key=value.
+    pub fn load(files: &[String], defines: &[String]) -> Result<Self, String> {
+        let mut vars = Vars::default();
+        for file in files {
+            let content = std::fs::read_to_string(file).map_err(|e| format!("failed to read vars {}: {}", file, e))?;
+            let table: toml::Table = toml::from_str(&content).map_err(|e| format!("failed to parse vars {}: {}", file, e))?;
+            for (key, value) in table {
+                if !is_var_name(&key) {
+                    return Err(format!("vars {}: '{}' is not a var name, expect letters, digits and '_' only", file, key));
+                }
+                let value = arg_value(&key, value).map_err(|e| format!("vars {}: {}", file, e))?;
+                vars.values.insert(key, value);
+            }
+            vars.args.extend(["--vars".to_string(), file.clone()]);
+        }
+        for define in defines {
+            match define.split_once('=') {
+                Some((key, value)) if is_var_name(key.trim()) => {
+                    vars.values.insert(key.trim().to_string(), ArgValue::Single(value.to_string()));
+                    vars.args.extend(["-D".to_string(), define.clone()]);
+                }
+                _ => return Err(format!("invalid -D '{}', expect key=value with letters, digits and '_' in key", define)),
+            }
+        }
+        Ok(vars)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The `--vars` and `-D` options giving these values.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Override the values of the shared inputs.
+    pub fn apply_shared(&self, shared_inputs: &mut HashMap<String, HashMap<String, ArgValue>>) {
+        for group in shared_inputs.values_mut() {
+            for (key, value) in group.iter_mut() {
+                if let Some(var) = self.values.get(key) {
+                    *value = var.clone();
+                }
+            }
+        }
+    }
+
+    /// Override the args of the input groups of `test`, and add the vars its
+    /// cmds refer to as `$key`. A test without inputs gets the single values
+    /// in its cmds and an input group "vars" for the lists and ranges.
+    pub fn apply(&self, test: &mut Test) {
+        let refers = |key: &str| {
+            test.cmds.iter().any(|cmd| {
+                let (Condition::Eq(expect) | Condition::Ne(expect)) = &cmd.condition;
+                refers_var(expect, key) || cmd.args.iter().any(|arg| refers_var(arg, key))
+            })
+        };
+        let referred: Vec<(&String, &ArgValue)> = self.values.iter().filter(|(key, _)| refers(key)).collect();
+
+        if test.inputs.is_empty() {
+            let singles: HashMap<String, ArgValue> = referred
+                .iter()
+                .filter(|(_, value)| matches!(value, ArgValue::Single(_)))
+                .map(|(key, value)| (key.to_string(), (*value).clone()))
+                .collect();
+            test.cmds = test.cmds.iter().map(|cmd| replace_cmd_vars(cmd, &singles)).collect();
+            let lists: HashMap<String, ArgValue> = referred
+                .iter()
+                .filter(|(_, value)| !matches!(value, ArgValue::Single(_)))
+                .map(|(key, value)| (key.to_string(), (*value).clone()))
+                .collect();
+            if !lists.is_empty() {
+                test.inputs.push(InputGroup {
+                    name: "vars".to_string(),
+                    args: lists,
+                    ..Default::default()
+                });
+            }
+            return;
+        }
+
+        for input in &mut test.inputs {
+            for (key, value) in input.args.iter_mut() {
+                // the shared input it refers to may not have it
+                let reference = match value {
+                    ArgValue::Single(s) => s.strip_prefix('$'),
+                    _ => None,
+                };
+                if let Some(var) = self.values.get(key).or_else(|| reference.and_then(|r| self.values.get(r))) {
+                    *value = var.clone();
+                }
+            }
+            for (key, value) in &referred {
+                input.args.entry(key.to_string()).or_insert_with(|| (*value).clone());
+            }
+            // a list overridden by a single value is no longer zipped
+            for group in &mut input.zip {
+                group.retain(|key| !matches!(input.args.get(key), Some(ArgValue::Single(_))));
+            }
+            input.zip.retain(|group| !group.is_empty());
+        }
+    }
+}
+
+impl fmt::Display for Vars {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values: Vec<String> = self
+            .values
+            .iter()
+            .map(|(key, value)| match value {
+                ArgValue::Single(s) => format!("{}={}", key, s),
+                ArgValue::List(items) => format!("{}=[{}]", key, items.join(", ")),
+                ArgValue::Range(range) => format!("{}=[{}]", key, range.values().join(", ")),
+            })
+            .collect();
+        write!(f, "{}", values.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TagFilter;
+
+    fn test(content: &str) -> Test {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn test_load_vars() {
+        let path = std::env::temp_dir().join(format!("hitest_vars_{}.toml", std::process::id()));
+        std::fs::write(&path, "len = 64\nval = \"7\"\noff = [0, 4]\naddr = { start = \"0x1000\", end = \"0x2000\", step = \"0x800\" }\n").unwrap();
+        let files = [path.display().to_string()];
+        let vars = Vars::load(&files, &["val=9".to_string(), "msg='a=b'".to_string()]).unwrap();
+        // -D wins over the file
+        assert_eq!(vars.to_string(), "addr=[0x1000, 0x1800, 0x2000] len=64 msg='a=b' off=[0, 4] val=9");
+        assert_eq!(vars.args(), ["--vars", &files[0], "-D", "val=9", "-D", "msg='a=b'"]);
+
+        assert!(Vars::load(&[], &["val".to_string()]).is_err());
+        assert!(Vars::load(&[], &["=1".to_string()]).is_err());
+        // `$mem-idx` would be left as it is
+        assert!(Vars::load(&[], &["mem-idx=1".to_string()]).is_err());
+        std::fs::write(&path, "off = [[0]]\n").unwrap();
+        assert!(Vars::load(&files, &[]).is_err());
+        std::fs::write(&path, "\"dev.id\" = 1\n").unwrap();
+        assert!(Vars::load(&files, &[]).unwrap_err().contains("'dev.id' is not a var name"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_apply_vars() {
+        let vars = Vars::load(&[], &["val=9".to_string(), "len=8".to_string(), "off=12".to_string()]).unwrap();
+        let mut shared = HashMap::from([("offs".to_string(), HashMap::from([("off".to_string(), ArgValue::List(vec!["0".to_string(), "4".to_string()]))]))]);
+        vars.apply_shared(&mut shared);
+        assert_eq!(shared["offs"]["off"], ArgValue::Single("12".to_string()));
+
+        let mut zipped = test(
+            r#"
+            name = "rw"
+            inputs = [ { name = "i", zip = [["off", "val"]], args = { off = "$off", val = ["1", "2"] } } ]
+            cmds = [ { opfunc = "Call_read32", expect_eq = "$val", args = ["off=$off", "len=$len"] } ]
+            "#,
+        );
+        vars.apply(&mut zipped);
+        let input = &zipped.inputs[0];
+        assert_eq!(input.args["val"], ArgValue::Single("9".to_string()));
+        assert_eq!(input.args["off"], ArgValue::Single("12".to_string()));
+        assert_eq!(input.args["len"], ArgValue::Single("8".to_string()));
+        assert!(input.zip.is_empty());
+
+        let mut plain = test(
+            r#"
+            name = "rw"
+            cmds = [ { opfunc = "Call_read32", expect_eq = "$!val", args = ["off=$off"] } ]
+            "#,
+        );
+        vars.apply(&mut plain);
+        assert!(plain.inputs.is_empty());
+        assert_eq!(plain.cmds[0].args, ["off=12"]);
+        assert_eq!(plain.cmds[0].condition, Condition::Ne("9".to_string()));
+    }
+
+    #[test]
+    fn test_apply_list_vars() {
+        let path = std::env::temp_dir().join(format!("hitest_vars_list_{}.toml", std::process::id()));
+        std::fs::write(&path, "off = [0, 4]\n").unwrap();
+        let vars = Vars::load(&[path.display().to_string()], &[]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut plain = test(
+            r#"
+            name = "rw"
+            cmds = [ { opfunc = "Call_read32", expect_eq = 0, args = ["off=$off"] } ]
+            "#,
+        );
+        vars.apply(&mut plain);
+        assert_eq!(plain.inputs[0].name, "vars");
+        let names: Vec<String> = plain.expand(&TagFilter::default()).into_iter().map(|test| test.name).collect();
+        assert_eq!(names, ["rw_vars_off=0", "rw_vars_off=4"]);
+        // not referred, nothing to add
+        let mut other = test(
+            r#"
+            name = "other"
+            cmds = [ { opfunc = "Call_free", expect_eq = 0, args = ["mem_idx=1"] } ]
+            "#,
+        );
+        vars.apply(&mut other);
+        assert!(other.inputs.is_empty());
+    }
+
+    #[test]
+    fn test_apply_whole_var_name() {
+        let vars = Vars::load(&[], &["len=8".to_string()]).unwrap();
+        let mut listed = test(
+            r#"
+            name = "rw"
+            inputs = [ { name = "i", args = { len2 = ["16", "32"] } } ]
+            cmds = [ { opfunc = "Call_malloc", expect_eq = 0, args = ["len=$len2", "mem_idx=$length"] } ]
+            "#,
+        );
+        vars.apply(&mut listed);
+        // len is not referred by $len2 or $length, so not added
+        assert!(!listed.inputs[0].args.contains_key("len"));
+        let names: Vec<String> = listed.expand(&TagFilter::default()).into_iter().map(|test| test.name).collect();
+        assert_eq!(names.len(), 2);
+
+        let mut plain = test(
+            r#"
+            name = "rw"
+            cmds = [ { opfunc = "Call_malloc", expect_eq = 0, args = ["len=$len2", "size=$len"] } ]
+            "#,
+        );
+        vars.apply(&mut plain);
+        assert_eq!(plain.cmds[0].args, ["len=$len2", "size=8"]);
+    }
+}
@@ -7,13 +7,16 @@ use std::{
     ffi::CString,
     fs,
     os::raw::c_longlong,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 use toml;
 mod error;
 pub use error::LibError;
 mod perf;
-use perf::Perf;
+pub use perf::Perf;
 
 #[derive(Deserialize)]
 struct LibConfig {
@@ -24,6 +27,9 @@ struct LibConfig {
 struct LibFunc {
     name: String,
     paras: Vec<String>,
+    // the wrapper takes the shared page as a fourth argument
+    #[serde(default)]
+    shared: bool,
 }
 
 #[derive(Deserialize)]
@@ -38,6 +44,10 @@ thread_local! {
     static C_STRINGS: RefCell<Vec<CString>> = RefCell::new(Vec::new());
 }
 
+const SHARED_PAGE_SIZE: usize = 512;
+// process-wide page for handing resources between threads, every access must be atomic
+static SHARED_PAGE: [AtomicU64; SHARED_PAGE_SIZE] = [const { AtomicU64::new(0) }; SHARED_PAGE_SIZE];
+
 //  4k bytes buffer for api communication, buffer of parameters, number of parameters, and buffer of return value.
 type FnPtr = extern "C" fn(
     *mut u64,   // uint64_t* param_page, for apis communication
@@ -45,19 +55,71 @@ type FnPtr = extern "C" fn(
     c_longlong, // int params_len
 ) -> c_longlong;
 
+// FnPtr with the shared page, for the funcs configured with `shared = true`
+type SharedFnPtr = extern "C" fn(
+    *mut u64,   // uint64_t* param_page
+    *const i64, // const uint64_t* params
+    c_longlong, // int params_len
+    *mut u64,   // uint64_t* shared_page, shared by all threads, use atomic access only
+) -> c_longlong;
+
 pub struct FnAttr {
     fnptr: FnPtr,
     paras: Vec<String>,
+    shared: bool,
+}
+
+/// Value of the slot `idx` of the shared page.
+pub fn shared_slot(idx: usize) -> Option<u64> {
+    SHARED_PAGE.get(idx).map(|slot| slot.load(Ordering::SeqCst))
+}
+
+/// A decimal or "0x" hex number, those above i64::MAX keep their u64 bits.
+pub fn parse_num(s: &str) -> Option<i64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(|num| num as i64),
+        None => s
+            .parse::<i64>()
+            .ok()
+            .or_else(|| s.parse::<u64>().ok().map(|num| num as i64)),
+    }
+}
+
+// "@shared[3]" -> 3
+fn parse_shared_idx(para: &str) -> Option<Result<usize, String>> {
+    let idx = para.strip_prefix("@shared[")?.strip_suffix(']')?;
+    Some(match idx.trim().parse::<usize>() {
+        Ok(idx) if idx < SHARED_PAGE_SIZE => Ok(idx),
+        _ => Err(format!(
+            "invalid shared slot [{}], the index limit is [0, {})",
+            idx, SHARED_PAGE_SIZE
+        )),
+    })
 }
 
 impl FnAttr {
-    fn new(fnptr: FnPtr, paras: Vec<String>) -> Self {
-        FnAttr { fnptr, paras }
+    fn new(fnptr: FnPtr, paras: Vec<String>, shared: bool) -> Self {
+        FnAttr {
+            fnptr,
+            paras,
+            shared,
+        }
     }
 
     fn run(&self, params: &[i64]) -> i64 {
         TLS_PAGE.with(|addr| {
             let mut addr = addr.borrow_mut();
+            if self.shared {
+                // the symbol was declared with the four argument ABI in the lib config
+                let fnptr: SharedFnPtr = unsafe { std::mem::transmute(self.fnptr) };
+                // AtomicU64 has the same in-memory representation as u64
+                return fnptr(
+                    addr.as_mut_ptr(),
+                    params.as_ptr(),
+                    params.len() as c_longlong,
+                    SHARED_PAGE.as_ptr() as *mut u64,
+                ) as i64;
+            }
             (self.fnptr)(
                 addr.as_mut_ptr(),
                 params.as_ptr(),
@@ -82,11 +144,12 @@ impl FnAttr {
             let mut succ = false;
             for value in config_params {
                 if let Some(para) = value.strip_prefix(&format!("{}=", key)) {
-                    if let Ok(num) = if para.starts_with("0x") || para.starts_with("0X") {
-                        i64::from_str_radix(&para[2..], 16)
-                    } else {
-                        para.parse::<i64>()
-                    } {
+                    if let Some(idx) = parse_shared_idx(para) {
+                        // read when the cmd runs, so it sees what other threads stored
+                        params.push(SHARED_PAGE[idx?].load(Ordering::SeqCst) as i64);
+                        succ = true;
+                        break;
+                    } else if let Some(num) = parse_num(para) {
                         params.push(num);
                         succ = true;
                         break;
@@ -157,6 +220,11 @@ impl LibParse {
         Ok((ret, perf))
     }
 
+    /// Parameter names of the function, as declared in the lib config.
+    pub fn params(&self, fn_name: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(self.get_func(fn_name)?.paras.clone())
+    }
+
     pub fn init(config: &str) -> Result<(), Box<dyn Error>> {
         unsafe {
             INIT.call_once(|| {
@@ -197,7 +265,7 @@ impl LibParse {
                 let c_func_name = CString::new(func_name.clone())?;
                 let func_ptr: Symbol<FnPtr> = unsafe { lib_arc.get(c_func_name.as_bytes()) }
                     .map_err(|_| LibError::FuncNotFound(func_name.clone()))?;
-                let func_attr = FnAttr::new(*func_ptr, func.paras);
+                let func_attr = FnAttr::new(*func_ptr, func.paras, func.shared);
                 funcs.insert(func_name, Arc::new(Box::new(func_attr)));
             }
         }
@@ -235,6 +303,13 @@ mod tests {
                 if (len!=2) return -1;
                 return param[0] + param[1];
             }
+
+            int shared_put(long long *page, const long long *param, long long len,
+                           unsigned long long *shared) {
+                if (len!=2) return -1;
+                __atomic_store_n(&shared[param[0]], param[1], __ATOMIC_SEQ_CST);
+                return 0;
+            }
         "#;
         c_file.write_all(c_content.as_bytes()).unwrap();
 
@@ -258,7 +333,8 @@ mod tests {
             [[libs]]
             path = "{}"
             funcs = [
-                {{ name = "test_func", paras = ["param1", "param2"] }}
+                {{ name = "test_func", paras = ["param1", "param2"] }},
+                {{ name = "shared_put", paras = ["slot", "value"], shared = true }}
             ]
         "#, so_path_display);
 
@@ -271,7 +347,8 @@ mod tests {
     fn test_parse_params() {
         let fn_attr = FnAttr {
             fnptr: mock_fn,
-            paras: vec!["param1".to_string(), "param2".to_string()]
+            paras: vec!["param1".to_string(), "param2".to_string()],
+            shared: false,
         };
 
         let params = vec!["param1=123".to_string(), "param2=456".to_string()];
@@ -279,6 +356,9 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), vec![123, 456]);
 
+        let params = vec!["param1=0xffffffffffffffff".to_string(), "param2=9223372036854775808".to_string()];
+        assert_eq!(fn_attr.parse_params(&params).unwrap(), vec![-1, i64::MIN]);
+
         let params = vec!["param1=123".to_string()];
         assert!(fn_attr.parse_params(&params).is_err());
     }
@@ -295,6 +375,7 @@ mod tests {
         let parser = parser.unwrap();
         assert!(parser.get_func("test_func").is_ok());
         assert!(parser.get_func("non_exist_func").is_err());
+        assert_eq!(parser.params("test_func").unwrap(), ["param1", "param2"]);
     }
 
     #[test]
@@ -306,4 +387,23 @@ mod tests {
         println!("res=={}", res);
         assert!(res == 579i64);
     }
+
+    #[test]
+    fn test_shared_page() {
+        let (config_file, _temp_dir) = create_test_lib_config();
+        let parser = LibParse::new(config_file.path().to_str().unwrap()).unwrap();
+        let put = vec!["slot=3".to_string(), "value=100".to_string()];
+        // stored by another thread, the thread local page does not see it
+        std::thread::scope(|scope| {
+            scope.spawn(|| parser.execute("shared_put".to_string(), &put).unwrap());
+        });
+        assert_eq!(shared_slot(3), Some(100));
+
+        let params = vec!["param1=@shared[3]".to_string(), "param2=1".to_string()];
+        assert_eq!(parser.execute("test_func".to_string(), &params).unwrap(), 101);
+
+        let params = vec!["param1=@shared[512]".to_string(), "param2=1".to_string()];
+        assert!(parser.execute("test_func".to_string(), &params).is_err());
+        assert_eq!(shared_slot(512), None);
+    }
 }
@@ -24,6 +24,12 @@ impl std::fmt::Display for Perf {
     }
 }
 
+impl Default for Perf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Perf {
     pub fn new() -> Self {
         Self {
@@ -36,6 +42,11 @@ impl Perf {
         }
     }
 
+    /// Time from `new` to the last `record`.
+    pub fn duration(&self) -> time::Duration {
+        self.duration
+    }
+
     pub fn record(&mut self) {
         #[cfg(unix)]
         {
@@ -15,6 +15,10 @@
 #define EXPORT_FUNC(func_name, ...) \
     EXPORT int Call_##func_name(uint64_t *param_page, const uint64_t *params, int params_len)
 
+// the exported function also gets the shared page, set `shared = true` for it in the lib config
+#define EXPORT_SHARED_FUNC(func_name, ...) \
+    EXPORT int Call_##func_name(uint64_t *param_page, const uint64_t *params, int params_len, uint64_t *shared_page)
+
 #define CHECK_PARAM_LEN(expected)                                                                                 \
     const static int param_idx = 0;                                                                               \
     do                                                                                                            \
@@ -74,4 +78,31 @@
         param_page[param_idx] = (uint64_t)val;                                     \
     } while (0)
 
+#define GET_SHARED_IDX(type, name, param_idx)                                                        \
+    type name;                                                                                       \
+    do                                                                                               \
+    {                                                                                                \
+        const int64_t __idx = (int64_t)params[param_idx];                                            \
+        if (__idx < 0 || __idx >= MAX_IDX)                                                           \
+        {                                                                                            \
+            fprintf(stderr, "[%s] shared [%s=%lld] out of range! the index limit is [0, 512) \n",    \
+                    __func__, #name, (long long)__idx);                                              \
+            return -12;                                                                              \
+        }                                                                                            \
+        name = (type)__atomic_load_n(&shared_page[__idx], __ATOMIC_SEQ_CST);                         \
+    } while (0)
+
+#define SET_SHARED_IDX(param_idx, val)                                                               \
+    do                                                                                               \
+    {                                                                                                \
+        const int64_t __idx = (int64_t)(param_idx);                                                  \
+        if (__idx < 0 || __idx >= MAX_IDX)                                                           \
+        {                                                                                            \
+            fprintf(stderr, "[%s] SHARED_IDX %lld out of range! the index limit is [0, 512) \n",     \
+                    __func__, (long long)__idx);                                                     \
+            return -12;                                                                              \
+        }                                                                                            \
+        __atomic_store_n(&shared_page[__idx], (uint64_t)val, __ATOMIC_SEQ_CST);                      \
+    } while (0)
+
 #endif // EXPORT_FUNCTION_H
@@ -62,18 +62,21 @@ def parse_export_funcs(source_path: str) -> list:
         len_content = len(content)
 
         while pos < len_content:
-            macro_start = content.find("EXPORT_FUNC(", pos)
-            if macro_start == -1:
+            # 找到下一个EXPORT_FUNC或EXPORT_SHARED_FUNC
+            found = [(content.find(m, pos), m) for m in ("EXPORT_FUNC(", "EXPORT_SHARED_FUNC(")]
+            found = [f for f in found if f[0] != -1]
+            if not found:
                 break
+            macro_start, macro = min(found)
 
             # Skip processing if the macro is part of a #define
             line_start = content.rfind('\n', 0, macro_start) + 1
             if content[line_start:macro_start].strip().startswith("#define"):
-                pos = macro_start + len("EXPORT_FUNC(")
+                pos = macro_start + len(macro)
                 continue
 
             # Parse arguments within parentheses
-            pos = macro_start + len("EXPORT_FUNC(")
+            pos = macro_start + len(macro)
             depth = 1
             while pos < len_content and depth > 0:
                 char = content[pos]
@@ -84,7 +87,7 @@ def parse_export_funcs(source_path: str) -> list:
                 pos += 1
 
             # Extract and process raw arguments
-            raw_args = content[macro_start+len("EXPORT_FUNC("):pos-1].strip()
+            raw_args = content[macro_start+len(macro):pos-1].strip()
             if ',' not in raw_args:
                 func_name = raw_args
                 params = []
@@ -95,7 +98,8 @@ def parse_export_funcs(source_path: str) -> list:
 
             configs.append({
                 "name": f"Call_{func_name}",
-                "params": params  # Preserve original parameter names without quotes
+                "params": params,  # Preserve original parameter names without quotes
+                "shared": macro == "EXPORT_SHARED_FUNC("
             })
 
         return configs
@@ -134,6 +138,8 @@ def generate_toml(configs: list, output_path: str, file_name: str, lib_dir: str
         for p in cfg["params"]:
             paras.append(tomlkit.string(p))  # Let tomlkit handle string quoting
         func.append("paras", paras)
+        if cfg["shared"]:
+            func.append("shared", True)
 
         funcs.append(func)
 